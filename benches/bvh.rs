@@ -0,0 +1,72 @@
+// Compares the linear search over all shapes with the bounding volume hierarchy.
+// Run with `cargo bench`.
+use nrtrt::material::Material;
+use nrtrt::ray::Ray;
+use nrtrt::shapes::Sphere;
+use nrtrt::vector::Vector;
+use nrtrt::world::World;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const NUM_OF_RAYS: usize = 20_000;
+
+fn get_world(num_of_spheres: usize) -> World
+{
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut world = World::new();
+    for _ in 0..num_of_spheres
+    {
+        world.add_shape(Arc::new(Sphere{radius: rng.gen_range(0.05, 0.5),
+                                        position: Vector{x: rng.gen_range(-20., 20.),
+                                                         y: rng.gen_range(-20., 20.),
+                                                         z: rng.gen_range(-40., -10.)},
+                                        material: Material::default()}));
+    }
+    world
+}
+
+fn get_rays() -> Vec<Ray>
+{
+    let mut rng = StdRng::seed_from_u64(2);
+    (0..NUM_OF_RAYS).map(|_| {
+        let direction = Vector{x: rng.gen_range(-0.6, 0.6), y: rng.gen_range(-0.6, 0.6), z: -1.};
+        Ray::new(&Vector{x: 0., y: 0., z: 5.}, &direction)
+    }).collect()
+}
+
+// Returns the time of the fastest of a few runs and the number of collisions found
+fn measure<F>(rays: &[Ray], query: F) -> (Duration, usize)
+    where F: Fn(&Ray) -> bool
+{
+    let mut best = Duration::from_secs(u64::MAX);
+    let mut num_of_collisions = 0;
+    for _ in 0..3
+    {
+        let start = Instant::now();
+        num_of_collisions = rays.iter().filter(|ray| query(ray)).count();
+        best = best.min(start.elapsed());
+    }
+    (best, num_of_collisions)
+}
+
+fn main()
+{
+    let rays = get_rays();
+    println!("{:>8} {:>14} {:>14} {:>9}", "shapes", "linear", "bvh", "speedup");
+    for num_of_spheres in [10, 100, 1_000, 10_000].iter()
+    {
+        let mut world = get_world(*num_of_spheres);
+        let (linear_time, linear_collisions) = measure(&rays, |ray| world.item_that_collide_linear(ray).is_some());
+
+        let build_start = Instant::now();
+        world.build_bvh();
+        let build_time = build_start.elapsed();
+        let (bvh_time, bvh_collisions) = measure(&rays, |ray| world.item_that_collide(ray).is_some());
+        assert_eq!(linear_collisions, bvh_collisions);
+
+        println!("{:>8} {:>12.2?} {:>14.2?} {:>8.1}x   (build {:.2?})",
+                 num_of_spheres, linear_time, bvh_time,
+                 linear_time.as_secs_f64() / bvh_time.as_secs_f64(), build_time);
+    }
+}
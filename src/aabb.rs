@@ -0,0 +1,156 @@
+use crate::ray::Ray;
+use crate::vector::Vector;
+use std::f64;
+
+// Axis aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb
+{
+    pub min: Vector,
+    pub max: Vector,
+}
+
+impl Aabb
+{
+    pub fn new(min: &Vector, max: &Vector) -> Aabb
+    {
+        Aabb{
+            min: *min,
+            max: *max
+        }
+    }
+
+    // Box that contains nothing, so that the union with any other box is the other box
+    pub fn empty() -> Aabb
+    {
+        Aabb{
+            min: Vector{x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY},
+            max: Vector{x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY}
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb
+    {
+        Aabb{
+            min: self.min.min(other.min),
+            max: self.max.max(other.max)
+        }
+    }
+
+    pub fn grow(&self, point: &Vector) -> Aabb
+    {
+        Aabb{
+            min: self.min.min(*point),
+            max: self.max.max(*point)
+        }
+    }
+
+    // Checks if the point is inside of the box grown by tolerance in every direction
+    pub fn contains(&self, point: &Vector, tolerance: f64) -> bool
+    {
+        (0..3).all(|axis| point.axis(axis) >= self.min.axis(axis) - tolerance && point.axis(axis) <= self.max.axis(axis) + tolerance)
+    }
+
+    pub fn centroid(&self) -> Vector
+    {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64
+    {
+        let extent = self.max - self.min;
+        if extent.x < 0. || extent.y < 0. || extent.z < 0.
+        {
+            return 0.;
+        }
+        2. * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    // Slab test. Returns the distance from the start of the ray to the point where it enters the box
+    // (0 if it starts inside), as long as that is not further than max_distance
+    pub fn intersect(&self, ray: &Ray, max_distance: f64) -> Option<f64>
+    {
+        let mut t_min = 0.;
+        let mut t_max = f64::INFINITY;
+        for axis in 0..3
+        {
+            let inverse_direction = 1. / ray.direction.axis(axis);
+            let mut t_0 = (self.min.axis(axis) - ray.start_position.axis(axis)) * inverse_direction;
+            let mut t_1 = (self.max.axis(axis) - ray.start_position.axis(axis)) * inverse_direction;
+            if inverse_direction < 0.
+            {
+                std::mem::swap(&mut t_0, &mut t_1);
+            }
+            // f64::max/min ignore NaN, which appears when the ray starts exactly on a parallel slab
+            t_min = t_0.max(t_min);
+            t_max = t_1.min(t_max);
+            if t_max < t_min
+            {
+                return None;
+            }
+        }
+        let distance = t_min * ray.direction.distance();
+        if distance > max_distance
+        {
+            return None;
+        }
+        Some(distance)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::aabb::Aabb;
+    use crate::ray::Ray;
+    use crate::vector::Vector;
+    use assert_approx_eq::assert_approx_eq;
+    use std::f64;
+
+    fn get_box() -> Aabb
+    {
+        Aabb::new(&Vector{x: -1., y: -1., z: -1.}, &Vector{x: 1., y: 2., z: 3.})
+    }
+
+    #[test]
+    fn union_and_surface_area()
+    {
+        let aabb = Aabb::empty().union(&get_box()).grow(&Vector{x: 3., y: 0., z: 0.});
+        assert_approx_eq!(aabb.min.x, -1.);
+        assert_approx_eq!(aabb.max.x, 3.);
+        assert_approx_eq!(aabb.max.z, 3.);
+        // 4 x 3 x 4 box
+        assert_approx_eq!(aabb.surface_area(), 2. * (12. + 12. + 16.));
+        assert_approx_eq!(Aabb::empty().surface_area(), 0.);
+    }
+
+    #[test]
+    fn contains()
+    {
+        let aabb = get_box();
+        assert!(aabb.contains(&Vector{x: 0., y: 2., z: 0.}, 0.));
+        assert!(!aabb.contains(&Vector{x: 0., y: 2.1, z: 0.}, 0.));
+        assert!(aabb.contains(&Vector{x: 0., y: 2.1, z: 0.}, 0.2));
+    }
+
+    #[test]
+    fn intersect()
+    {
+        let aabb = get_box();
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 10.}, &Vector{x: 0., y: 0., z: -2.});
+        assert_approx_eq!(aabb.intersect(&ray, f64::MAX).unwrap(), 7.);
+        assert!(aabb.intersect(&ray, 5.).is_none());
+
+        // Starting inside of the box
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 0.}, &Vector{x: 1., y: 1., z: 0.});
+        assert_approx_eq!(aabb.intersect(&ray, f64::MAX).unwrap(), 0.);
+
+        // Pointing away from the box
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 10.}, &Vector{x: 0., y: 0., z: 1.});
+        assert!(aabb.intersect(&ray, f64::MAX).is_none());
+
+        // Passing next to the box
+        let ray = Ray::new(&Vector{x: 2., y: 0., z: 10.}, &Vector{x: 0., y: 0., z: -1.});
+        assert!(aabb.intersect(&ray, f64::MAX).is_none());
+    }
+}
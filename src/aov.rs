@@ -0,0 +1,185 @@
+// Arbitrary output variables: extra images with the geometry of the first hit of every pixel,
+// i.e. for compositing and as the guide images of denoisers. Every pass is saved as a separate
+// image next to the rendered one.
+use crate::pixel::{Pixel, Color};
+use crate::tonemap::linear_to_srgb;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov
+{
+    // Distance from the camera to the hit
+    Depth,
+    // Shading normal in the world space, turned towards the camera
+    Normal,
+    // Color of the material, without any lighting
+    Albedo,
+    // Which shape was hit, 0 is the sky
+    ObjectId,
+    // Which material was hit, 0 is the sky or a material without the id
+    MaterialId,
+}
+
+impl Aov
+{
+    pub const ALL: [Aov; 5] = [Aov::Depth, Aov::Normal, Aov::Albedo, Aov::ObjectId, Aov::MaterialId];
+
+    // Names used on the command line and in the names of the saved files
+    pub fn from_name(name: &str) -> Option<Aov>
+    {
+        Aov::ALL.iter().copied().find(|aov| aov.name() == name)
+    }
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object",
+            Aov::MaterialId => "material",
+        }
+    }
+
+    // Path of the pass saved next to the image, i.e. render_depth.exr for render.exr
+    pub fn path(self, image_path: &str) -> String
+    {
+        let path = Path::new(image_path);
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+        let mut file_name = format!("{}_{}", stem, self.name());
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str())
+        {
+            file_name = format!("{}.{}", file_name, extension);
+        }
+        path.with_file_name(file_name).to_string_lossy().into_owned()
+    }
+
+    // Values of the pass for every pixel. Raw values are meant for the float formats: the depth in
+    // all channels, the normal's x, y, z as r, g, b, the linear albedo and the ids as whole numbers.
+    // Otherwise they are mapped to 0..1 to be viewed: the depth as nearest / depth, so the nearest hit
+    // is white and far away ones (i.e. the horizon) fade to black, the normal from -1..1 to 0..1,
+    // the albedo is sRGB encoded and every id gets its own color.
+    pub fn colors(self, pixels: &[Pixel], raw: bool) -> Vec<Color>
+    {
+        let grey = |value: f64| Color{r: value, g: value, b: value};
+        match self
+        {
+            Aov::Depth if raw => pixels.iter().map(|pixel| grey(pixel.depth)).collect(),
+            Aov::Depth => {
+                let nearest = pixels.iter()
+                    .filter(|pixel| pixel.object_id != 0)
+                    .fold(f64::MAX, |nearest, pixel| nearest.min(pixel.depth));
+                pixels.iter().map(|pixel| {
+                    if pixel.object_id == 0
+                    {
+                        return Color::new();
+                    }
+                    grey(nearest / pixel.depth)
+                }).collect()
+            },
+            Aov::Normal => pixels.iter().map(|pixel| {
+                let normal = Color{r: pixel.normal.x, g: pixel.normal.y, b: pixel.normal.z};
+                if raw || pixel.object_id == 0
+                {
+                    return normal;
+                }
+                normal * 0.5 + grey(0.5)
+            }).collect(),
+            Aov::Albedo if raw => pixels.iter().map(|pixel| pixel.albedo).collect(),
+            Aov::Albedo => pixels.iter().map(|pixel| Color{
+                r: linear_to_srgb(pixel.albedo.r.clamp(0., 1.)),
+                g: linear_to_srgb(pixel.albedo.g.clamp(0., 1.)),
+                b: linear_to_srgb(pixel.albedo.b.clamp(0., 1.))
+            }).collect(),
+            Aov::ObjectId => pixels.iter().map(|pixel| id_color(pixel.object_id, raw)).collect(),
+            Aov::MaterialId => pixels.iter().map(|pixel| id_color(pixel.material_id, raw)).collect(),
+        }
+    }
+}
+
+// Ids are exact in 32 bit floats up to 2^24. The visible colors come from a hash of the id,
+// so that neighbouring ids differ, 0 stays black.
+fn id_color(id: u32, raw: bool) -> Color
+{
+    if raw
+    {
+        return Color{r: id as f64, g: id as f64, b: id as f64};
+    }
+    if id == 0
+    {
+        return Color::new();
+    }
+    let hash = id.wrapping_mul(0x9E37_79B9).rotate_left(16).wrapping_mul(0x85EB_CA6B);
+    let channel = |shift: u32| (((hash >> shift) & 0xFF) as f64 / 255.) * 0.8 + 0.2;
+    Color{r: channel(0), g: channel(8), b: channel(16)}
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::aov::Aov;
+    use crate::pixel::{Pixel, Color};
+    use crate::vector::Vector;
+    use assert_approx_eq::assert_approx_eq;
+
+    // Two hits at different distances and the sky
+    fn get_pixels() -> Vec<Pixel>
+    {
+        let mut near = Pixel::new();
+        near.depth = 2.;
+        near.normal = Vector{x: 0., y: -1., z: 0.};
+        near.albedo = Color{r: 0.5, g: 0.25, b: 1.};
+        near.object_id = 1;
+        near.material_id = 2;
+        let mut far = near;
+        far.depth = 6.;
+        far.object_id = 2;
+        vec![near, far, Pixel::new()]
+    }
+
+    #[test]
+    fn names_and_paths()
+    {
+        for aov in Aov::ALL.iter()
+        {
+            assert_eq!(Aov::from_name(aov.name()), Some(*aov));
+        }
+        assert_eq!(Aov::from_name("position"), None);
+        assert_eq!(Aov::Depth.path("image.png"), "image_depth.png");
+        assert_eq!(Aov::ObjectId.path("out/render.exr"), "out/render_object.exr");
+    }
+
+    #[test]
+    fn raw_values()
+    {
+        let pixels = get_pixels();
+        assert_eq!(Aov::Depth.colors(&pixels, true)[1], Color{r: 6., g: 6., b: 6.});
+        assert_eq!(Aov::Normal.colors(&pixels, true)[0], Color{r: 0., g: -1., b: 0.});
+        assert_eq!(Aov::Albedo.colors(&pixels, true)[0], Color{r: 0.5, g: 0.25, b: 1.});
+        let object_ids = Aov::ObjectId.colors(&pixels, true);
+        assert_eq!(object_ids.iter().map(|color| color.r).collect::<Vec<f64>>(), vec![1., 2., 0.]);
+        assert_eq!(Aov::MaterialId.colors(&pixels, true)[1].g, 2.);
+    }
+
+    #[test]
+    fn visible_values()
+    {
+        let pixels = get_pixels();
+        let depth = Aov::Depth.colors(&pixels, false);
+        assert_approx_eq!(depth[0].r, 1.);
+        assert_approx_eq!(depth[1].r, 1. / 3.);
+        assert_eq!(depth[2], Color::new());
+
+        let normal = Aov::Normal.colors(&pixels, false);
+        assert_approx_eq!(normal[0].g, 0.);
+        assert_approx_eq!(normal[0].r, 0.5);
+
+        // Different objects get different colors, the same materials the same ones
+        let object_ids = Aov::ObjectId.colors(&pixels, false);
+        assert_ne!(object_ids[0], object_ids[1]);
+        assert_eq!(object_ids[2], Color::new());
+        let material_ids = Aov::MaterialId.colors(&pixels, false);
+        assert_eq!(material_ids[0], material_ids[1]);
+    }
+}
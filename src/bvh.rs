@@ -0,0 +1,385 @@
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::vector::Vector;
+use std::f64;
+
+// Number of buckets that the centroids are sorted into when looking for the best split
+const NUM_OF_BINS: usize = 12;
+// Nodes with that many primitives (or less) are never split
+const MIN_LEAF_SIZE: usize = 2;
+// Nodes with more primitives than that are always split, even if SAH says otherwise
+const MAX_LEAF_SIZE: usize = 8;
+
+#[derive(Debug)]
+enum BvhNodeKind
+{
+    // Primitives indices[first..first + count]
+    Leaf{first: usize, count: usize},
+    Interior{left: usize, right: usize}
+}
+
+#[derive(Debug)]
+struct BvhNode
+{
+    bounds: Aabb,
+    kind: BvhNodeKind
+}
+
+// Bounding volume hierarchy built with the surface area heuristic. It only knows the bounding boxes
+// of the primitives, the primitives themselves are tested by the caller.
+pub struct Bvh
+{
+    nodes: Vec<BvhNode>,
+    // Indices of the primitives, ordered so that every leaf owns a continuous range
+    indices: Vec<usize>
+}
+
+#[derive(Clone, Copy)]
+struct Bin
+{
+    bounds: Aabb,
+    count: usize
+}
+
+impl Bvh
+{
+    pub fn build(bounds: &[Aabb]) -> Bvh
+    {
+        let mut bvh = Bvh{
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: (0..bounds.len()).collect()
+        };
+        if !bounds.is_empty()
+        {
+            let centroids: Vec<Vector> = bounds.iter().map(|aabb| aabb.centroid()).collect();
+            bvh.build_node(bounds, &centroids, 0, bounds.len());
+        }
+        bvh
+    }
+
+    // Recursively builds the node for indices[first..first + count] and returns its id
+    fn build_node(&mut self, bounds: &[Aabb], centroids: &[Vector], first: usize, count: usize) -> usize
+    {
+        let mut node_bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for index in self.indices[first..first + count].iter()
+        {
+            node_bounds = node_bounds.union(&bounds[*index]);
+            centroid_bounds = centroid_bounds.grow(&centroids[*index]);
+        }
+
+        let node_id = self.nodes.len();
+        self.nodes.push(BvhNode{
+            bounds: node_bounds,
+            kind: BvhNodeKind::Leaf{first, count}
+        });
+        if count <= MIN_LEAF_SIZE
+        {
+            return node_id;
+        }
+
+        let (axis, split_bin, split_cost) = match Bvh::find_best_split(bounds, centroids, &self.indices[first..first + count], &centroid_bounds)
+        {
+            Some(split) => split,
+            // All centroids are in the same place, there is no way to separate them
+            None => return node_id
+        };
+        // Cost of intersecting every primitive vs cost of the split (relative to the node's area)
+        let leaf_cost = count as f64 * node_bounds.surface_area();
+        if split_cost >= leaf_cost && count <= MAX_LEAF_SIZE
+        {
+            return node_id;
+        }
+
+        // Move the primitives that belong to the left child to the front
+        let mut middle = first;
+        for idx in first..first + count
+        {
+            let centroid = centroids[self.indices[idx]];
+            if Bvh::bin_of(&centroid, &centroid_bounds, axis) <= split_bin
+            {
+                self.indices.swap(idx, middle);
+                middle += 1;
+            }
+        }
+
+        let left = self.build_node(bounds, centroids, first, middle - first);
+        let right = self.build_node(bounds, centroids, middle, first + count - middle);
+        self.nodes[node_id].kind = BvhNodeKind::Interior{left, right};
+        node_id
+    }
+
+    fn bin_of(centroid: &Vector, centroid_bounds: &Aabb, axis: usize) -> usize
+    {
+        let extent = centroid_bounds.max.axis(axis) - centroid_bounds.min.axis(axis);
+        let relative_position = (centroid.axis(axis) - centroid_bounds.min.axis(axis)) / extent;
+        ((relative_position * NUM_OF_BINS as f64) as usize).min(NUM_OF_BINS - 1)
+    }
+
+    // Returns the axis and the last bin of the left child of the split with the lowest SAH cost, and that cost
+    fn find_best_split(bounds: &[Aabb], centroids: &[Vector], indices: &[usize], centroid_bounds: &Aabb) -> Option<(usize, usize, f64)>
+    {
+        let mut best_split: Option<(usize, usize, f64)> = None;
+        for axis in 0..3
+        {
+            if centroid_bounds.max.axis(axis) - centroid_bounds.min.axis(axis) <= 0.
+            {
+                continue;
+            }
+            let mut bins = [Bin{bounds: Aabb::empty(), count: 0}; NUM_OF_BINS];
+            for index in indices.iter()
+            {
+                let bin = &mut bins[Bvh::bin_of(&centroids[*index], centroid_bounds, axis)];
+                bin.bounds = bin.bounds.union(&bounds[*index]);
+                bin.count += 1;
+            }
+
+            // Area * count of everything on the right side of every split, swept from the right
+            let mut right_costs = [0.; NUM_OF_BINS];
+            let mut right_bounds = Aabb::empty();
+            let mut right_count = 0;
+            for bin in (1..NUM_OF_BINS).rev()
+            {
+                right_bounds = right_bounds.union(&bins[bin].bounds);
+                right_count += bins[bin].count;
+                right_costs[bin - 1] = right_bounds.surface_area() * right_count as f64;
+            }
+
+            let mut left_bounds = Aabb::empty();
+            let mut left_count = 0;
+            for bin in 0..NUM_OF_BINS - 1
+            {
+                left_bounds = left_bounds.union(&bins[bin].bounds);
+                left_count += bins[bin].count;
+                if left_count == 0 || left_count == indices.len()
+                {
+                    continue;
+                }
+                let cost = left_bounds.surface_area() * left_count as f64 + right_costs[bin];
+                if best_split.is_none_or(|(_, _, best_cost)| cost < best_cost)
+                {
+                    best_split = Some((axis, bin, cost));
+                }
+            }
+        }
+        best_split
+    }
+
+    // Finds the closest primitive that the ray hits. `intersect` is called with the index of every primitive
+    // whose box is hit closer than the closest hit so far and returns the distance to the primitive and
+    // any data the caller wants back.
+    pub fn closest_hit<T, F>(&self, ray: &Ray, max_distance: f64, mut intersect: F) -> Option<(usize, f64, T)>
+        where F: FnMut(usize) -> Option<(f64, T)>
+    {
+        let mut closest: Option<(usize, f64, T)> = None;
+        let mut smallest_distance = max_distance;
+        let mut stack: Vec<(usize, f64)> = Vec::with_capacity(64);
+        if let Some(root) = self.nodes.first()
+        {
+            if let Some(distance) = root.bounds.intersect(ray, smallest_distance)
+            {
+                stack.push((0, distance));
+            }
+        }
+        while let Some((node_id, entry_distance)) = stack.pop()
+        {
+            // Something closer was found since the node was pushed
+            if entry_distance > smallest_distance
+            {
+                continue;
+            }
+            match self.nodes[node_id].kind
+            {
+                BvhNodeKind::Leaf{first, count} =>
+                {
+                    for index in self.indices[first..first + count].iter()
+                    {
+                        if let Some((distance, data)) = intersect(*index)
+                        {
+                            if distance < smallest_distance
+                            {
+                                smallest_distance = distance;
+                                closest = Some((*index, distance, data));
+                            }
+                        }
+                    }
+                },
+                BvhNodeKind::Interior{left, right} =>
+                {
+                    let left_distance = self.nodes[left].bounds.intersect(ray, smallest_distance);
+                    let right_distance = self.nodes[right].bounds.intersect(ray, smallest_distance);
+                    // Push the further child first, so that the closer one is visited first
+                    match (left_distance, right_distance)
+                    {
+                        (Some(l), Some(r)) if l <= r => {
+                            stack.push((right, r));
+                            stack.push((left, l));
+                        },
+                        (Some(l), Some(r)) => {
+                            stack.push((left, l));
+                            stack.push((right, r));
+                        },
+                        (Some(l), None) => stack.push((left, l)),
+                        (None, Some(r)) => stack.push((right, r)),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    // Calls `visit` with the index of every primitive from the leaves whose box contains the point
+    // (with given tolerance). Other primitives of those leaves are visited too, `visit` has to check them.
+    pub fn visit_containing<F>(&self, point: &Vector, tolerance: f64, mut visit: F)
+        where F: FnMut(usize)
+    {
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        if !self.nodes.is_empty()
+        {
+            stack.push(0);
+        }
+        while let Some(node_id) = stack.pop()
+        {
+            let node = &self.nodes[node_id];
+            if !node.bounds.contains(point, tolerance)
+            {
+                continue;
+            }
+            match node.kind
+            {
+                BvhNodeKind::Leaf{first, count} => self.indices[first..first + count].iter().for_each(|index| visit(*index)),
+                BvhNodeKind::Interior{left, right} =>
+                {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+
+    // Returns true as soon as `intersect` reports a hit for any primitive whose box is hit by the ray
+    // within max_distance. Order of the visited primitives doesn't matter, so there is no sorting.
+    pub fn any_hit<F>(&self, ray: &Ray, max_distance: f64, mut intersect: F) -> bool
+        where F: FnMut(usize) -> bool
+    {
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        if !self.nodes.is_empty()
+        {
+            stack.push(0);
+        }
+        while let Some(node_id) = stack.pop()
+        {
+            let node = &self.nodes[node_id];
+            if node.bounds.intersect(ray, max_distance).is_none()
+            {
+                continue;
+            }
+            match node.kind
+            {
+                BvhNodeKind::Leaf{first, count} =>
+                {
+                    if self.indices[first..first + count].iter().any(|index| intersect(*index))
+                    {
+                        return true;
+                    }
+                },
+                BvhNodeKind::Interior{left, right} =>
+                {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::aabb::Aabb;
+    use crate::bvh::Bvh;
+    use crate::ray::Ray;
+    use crate::vector::Vector;
+    use std::f64;
+
+    // Row of unit boxes along the x axis, centered at x = 0, 2, 4...
+    fn get_boxes(count: usize) -> Vec<Aabb>
+    {
+        (0..count).map(|i| {
+            let center = Vector{x: 2. * i as f64, y: 0., z: 0.};
+            let half_size = Vector{x: 0.5, y: 0.5, z: 0.5};
+            Aabb::new(&(center - half_size), &(center + half_size))
+        }).collect()
+    }
+
+    #[test]
+    fn every_primitive_is_in_one_leaf()
+    {
+        let boxes = get_boxes(100);
+        let bvh = Bvh::build(&boxes);
+        let mut indices = bvh.indices.clone();
+        indices.sort();
+        assert_eq!(indices, (0..100).collect::<Vec<usize>>());
+        // The boxes are easy to separate, so the tree should be split down to small leaves
+        assert!(bvh.nodes.len() > 50);
+    }
+
+    #[test]
+    fn closest_hit()
+    {
+        let boxes = get_boxes(100);
+        let bvh = Bvh::build(&boxes);
+        // Ray along the row, starting between box 10 and 11 and going towards the higher ones
+        let ray = Ray::new(&Vector{x: 21., y: 0., z: 0.}, &Vector{x: 1., y: 0., z: 0.});
+        let mut num_of_tests = 0;
+        let hit = bvh.closest_hit(&ray, f64::MAX, |index| {
+            num_of_tests += 1;
+            boxes[index].intersect(&ray, f64::MAX).map(|distance| (distance, index))
+        });
+        let (index, distance, data) = hit.unwrap();
+        assert_eq!(index, 11);
+        assert_eq!(data, 11);
+        assert!((distance - 0.5).abs() < 1e-9);
+        // Boxes further than the first hit should not be tested
+        assert!(num_of_tests < 20);
+
+        let ray = Ray::new(&Vector{x: 21., y: 5., z: 0.}, &Vector{x: 1., y: 0., z: 0.});
+        assert!(bvh.closest_hit(&ray, f64::MAX, |index| boxes[index].intersect(&ray, f64::MAX).map(|d| (d, ()))).is_none());
+    }
+
+    #[test]
+    fn any_hit()
+    {
+        let boxes = get_boxes(100);
+        let bvh = Bvh::build(&boxes);
+        let ray = Ray::new(&Vector{x: 50., y: 10., z: 0.}, &Vector{x: 0., y: -1., z: 0.});
+        assert!(bvh.any_hit(&ray, f64::MAX, |index| boxes[index].intersect(&ray, f64::MAX).is_some()));
+        // The box is 9.5 units away
+        assert!(!bvh.any_hit(&ray, 9., |index| boxes[index].intersect(&ray, 9.).is_some()));
+        assert!(!Bvh::build(&[]).any_hit(&ray, f64::MAX, |_| true));
+    }
+
+    #[test]
+    fn visit_containing()
+    {
+        let boxes = get_boxes(100);
+        let bvh = Bvh::build(&boxes);
+        let mut visited = Vec::new();
+        bvh.visit_containing(&Vector{x: 40.3, y: 0., z: 0.}, 0., |index| visited.push(index));
+        assert!(visited.contains(&20));
+        assert!(visited.len() <= 8);
+
+        // Between two boxes
+        visited.clear();
+        bvh.visit_containing(&Vector{x: 41., y: 0., z: 0.}, 0.6, |index| visited.push(index));
+        assert!(visited.contains(&20));
+        assert!(visited.contains(&21));
+
+        // Outside of every box
+        visited.clear();
+        bvh.visit_containing(&Vector{x: 41., y: 5., z: 0.}, 0.6, |index| visited.push(index));
+        assert!(visited.is_empty());
+    }
+}
@@ -1,236 +1,312 @@
-use minifb::{Window, Key, WindowOptions};
-use image;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::thread;
 use crate::{
     world::World,
     pixel::{Pixel, Color},
-    ray::Ray,
+    output,
+    aov::Aov,
     vector::Vector,
-    lambertian::Lambertian,
-    raytree::*,
+    view::{View, Lens},
+    tracer::{Tracer, Tile},
+    settings::{RenderSettings, SettingsError},
 };
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum UpdateStatus{
-    NotFinished,
-    Finished,
-    AboutToExit
-}
-
-const FOV: f64 = 70.;
-// const MAX_RAY_DEPTH: u32 = 0;
-const MAX_RAY_DEPTH: u32 = 4;
-const NUM_OF_REFLECTED_RAYS: usize = 200;
-// How many rays should be send in each reflection
-// i.e. 0.75 -> first reflection will have 0.75 * NUM_OF_REFLECTED_RAYS,
-// second will have 0.75 of previous number and so on
-const SCATTERED_RAYS_FALLOFF: f64 = 0.75;
-const WIDTH: u32 = 400;
-const HEIGHT: u32 = 300;
-// const WIDTH: u32 = 200;
-// const HEIGHT: u32 = 200;
-const WIDTH_CHUNK: u32 = 20;
-const HEIGHT_CHUNK: u32 = 20;
-
 pub struct Camera{
+    settings: RenderSettings,
     buffer: Vec<Pixel>,
-    pub starting_point: Vector,
-    pub direction: Vector,
-    lambertian: Lambertian,
-    arena: RayArena,
-    window: Window,
+    pub view: View,
+    pub lens: Lens,
+    // Number of chunks that are already rendered
     pub chunk_num: u32,
     pub total_num_of_rays: u64
 }
 
 impl Camera{
-    pub fn new() -> Camera
-    {
-        Camera{
-            buffer: vec![Pixel::new(); (WIDTH * HEIGHT) as usize],
-            starting_point: Vector{x:0., y:0., z: 5.},
-            direction: Vector{
-                x: 0.,
-                y: 0.,
-                z: -1.
-            },
-            lambertian: Lambertian::new(NUM_OF_REFLECTED_RAYS),
-            arena: RayArena::new(MAX_RAY_DEPTH),
-            window: Window::new("nrtrt", WIDTH as usize, HEIGHT as usize, WindowOptions::default()).unwrap(),
+    // Fails if the settings are invalid, see RenderSettings::validate
+    pub fn new(settings: &RenderSettings) -> Result<Camera, SettingsError>
+    {
+        settings.validate()?;
+        Ok(Camera{
+            settings: *settings,
+            buffer: vec![Pixel::new(); (settings.width * settings.height) as usize],
+            view: View::look_in(&Vector{x: 0., y: 0., z: 5.}, &Vector{x: 0., y: 0., z: -1.}, &View::default_up()).unwrap(),
+            lens: Lens::pinhole(),
             chunk_num: 0,
             total_num_of_rays: 0
-        }
+        })
     }
 
-    fn get_pixel(&mut self, x: u32, y: u32) -> Option<&mut Pixel>
+    pub fn settings(&self) -> &RenderSettings
     {
-        self.buffer.get_mut((x + y * WIDTH) as usize)
+        &self.settings
     }
 
-    pub fn update(&mut self) -> UpdateStatus
+    // Returns true when every chunk of the image has been rendered
+    pub fn is_finished(&self) -> bool
     {
-        //If all chunks are rendered
-
-        //TODO: change that!
-        let mut temp_buffer: Vec<u32> = Vec::with_capacity((WIDTH * HEIGHT) as usize);
-        for pixel in 0..(WIDTH * HEIGHT)
-        {
-            temp_buffer.push(self.buffer[pixel as usize].color.to_u32());
-        }
-
-        self.window.update_with_buffer(&temp_buffer).unwrap();
-        match self.window.is_open() && !self.window.is_key_down(Key::Escape){
-            false => UpdateStatus::AboutToExit,
-            true => {
-                if (WIDTH / WIDTH_CHUNK) * (HEIGHT / HEIGHT_CHUNK) <= self.chunk_num{
-                    return UpdateStatus::Finished;
-                }                
-                return UpdateStatus::NotFinished;
-                }
-        }
+        self.settings.num_of_chunks() <= self.chunk_num
     }
 
-    pub fn save_image(&self){
-        // Lame method but works
-        let mut buffer = Vec::with_capacity((WIDTH * HEIGHT * 3) as usize);
-        for pixel in self.buffer.iter(){
-            let pixel_val = pixel.color.to_u32();
-            buffer.push(((pixel_val >> 16) & 255) as u8);
-            buffer.push(((pixel_val >> 8) & 255) as u8);
-            buffer.push((pixel_val & 255) as u8);
-        }
-        image::save_buffer("image.png", &buffer, WIDTH, HEIGHT, image::RGB(8)).unwrap();
-    }
-
-    pub fn shoot_primary_rays(&mut self, world: &World)
-    {
-        let pixel_to_pixel_angle = FOV / WIDTH as f64;
-        let first_pixel_angle_horizontal = (WIDTH as i32 / -2) as f64 * pixel_to_pixel_angle;
-        let first_pixel_angle_vertical = (HEIGHT as i32 / -2) as f64 * pixel_to_pixel_angle;
-        //Clear the arena
-        self.arena.nodes.clear();
-
-        let chunk_x = self.chunk_num % (WIDTH / WIDTH_CHUNK);
-        let start_x = WIDTH_CHUNK * chunk_x;
-        let end_x = WIDTH_CHUNK * (chunk_x + 1);
-        let chunk_y = self.chunk_num / (WIDTH / WIDTH_CHUNK);
-        let start_y = HEIGHT_CHUNK * chunk_y;
-        let end_y = HEIGHT_CHUNK * (chunk_y + 1);
-
-        for x in start_x..end_x
-        {
-            for y in start_y..end_y
-            {
-                let mut ray_direction = self.direction;
-                ray_direction.rotate_y(first_pixel_angle_horizontal + pixel_to_pixel_angle * x as f64);   //Rotate ray horizontally
-                ray_direction.rotate_x(first_pixel_angle_vertical + pixel_to_pixel_angle * y as f64);   //Rotate ray vertically
-
-
-                let ray = Ray::new(&self.starting_point, &ray_direction);
-                if let Some(_) = world.item_that_collide(&ray)
-                {
-                    // Create reflected rays and add them to the arena
-                    let node_id = self.arena.add_node(NodeId::Root, &Ray::new(&self.starting_point, &ray_direction));
-                    self.shoot_reflected_rays(world, &self.lambertian.get_offsets().clone(), node_id);
-                    let color = self.calculate_node_color(world, node_id);
-                    self.get_pixel(x, y).unwrap().color = color;
-                    // Remove the rays to save space
-                    self.total_num_of_rays += self.arena.nodes.len() as u64;
-                    self.arena.remove_node_with_childs(node_id);
-                }
-                else
-                {
-                    self.get_pixel(x, y).unwrap().color = Color{r: 128, g: 218, b: 235};
-                }
-            }
-        }
-        self.chunk_num += 1;
+    // Renders all chunks on num_of_threads worker threads without any window,
+    // i.e. for build servers and tests
+    pub fn render(&mut self, world: &World, num_of_threads: usize)
+    {
+        self.render_with_progress(world, num_of_threads, |_| true);
     }
 
-    fn shoot_reflected_rays(&mut self, world: &World, offsets: &Vec<Vector>, id: NodeId){
-        let ray_node_opt = self.arena.get_node(id);
-        //If parent exists
-        if let Some(ray_node) = ray_node_opt{
-            if ray_node.recursion_depth >= MAX_RAY_DEPTH{
-                return;
-            }
-            //If the collision occurred
-            if let Some((collision_shape, new_collision_point)) = world.item_that_collide(&ray_node.ray){
-                if collision_shape.is_specular(){
-                    let normal = collision_shape.normal_at_point(&new_collision_point).unwrap();
-                    let new_direction = ray_node.ray.direction.reflection(normal);
-                    let new_ray = Ray::new(&new_collision_point, &new_direction);
-                    let new_node_id = self.arena.add_node(id, &new_ray);
-                    self.shoot_reflected_rays(world, offsets, new_node_id);
-                }
-                else {
-                    // Calculate the number of required rays
-                    let mut num_of_rays = NUM_OF_REFLECTED_RAYS as f64;
-                    if ray_node.recursion_depth > 0 {
-                        let denominator = (ray_node.recursion_depth + 1) as f64 * SCATTERED_RAYS_FALLOFF;
-                        num_of_rays /= denominator;
-                    }
-                    //                println!("{} {}", num_of_rays, ray_node.recursion_depth);
-                    for (idx, offset) in offsets.iter().enumerate() {
-                        if idx > num_of_rays as usize {
+    // Renders all chunks on num_of_threads worker threads. Chunks are handed out to the workers one by one
+    // and every finished chunk is copied into the buffer as soon as it arrives, after which on_tile is called.
+    // When on_tile returns false the remaining chunks are abandoned.
+    pub fn render_with_progress<F>(&mut self, world: &World, num_of_threads: usize, mut on_tile: F)
+        where F: FnMut(&Camera) -> bool
+    {
+        self.chunk_num = 0;
+        let next_chunk = AtomicU32::new(0);
+        let abort = AtomicBool::new(false);
+        let (view, lens) = (self.view, self.lens);
+        let settings = self.settings;
+        let (sender, receiver) = mpsc::channel::<Tile>();
+
+        thread::scope(|scope| {
+            for _ in 0..num_of_threads.max(1){
+                let sender = sender.clone();
+                let (next_chunk, abort) = (&next_chunk, &abort);
+                scope.spawn(move || {
+                    let mut tracer = Tracer::new(world, &settings, &view, &lens);
+                    loop{
+                        let chunk_num = next_chunk.fetch_add(1, Ordering::Relaxed);
+                        if chunk_num >= settings.num_of_chunks() || abort.load(Ordering::Relaxed){
+                            break;
+                        }
+                        if sender.send(tracer.render_chunk(chunk_num)).is_err(){
                             break;
                         }
-                        let new_direction = collision_shape.normal_at_point(&new_collision_point).unwrap() + *offset;
-                        let new_ray = Ray::new(&new_collision_point, &new_direction);
-                        let new_node_id = self.arena.add_node(id, &new_ray);
-                        self.shoot_reflected_rays(world, offsets, new_node_id);
                     }
-                }
+                });
             }
-        }
-    }
+            // Only the workers keep the channel open now
+            drop(sender);
 
-    fn calculate_node_color(&self, world: &World, id: NodeId) -> Color{
-        if let NodeId::Parent(_) = id{
-            if let Some(node) = self.arena.get_node(id)
-            {
-                // If it is the last ray, calculate the light that is reaching this point
-                if node.child.len() == 0{
-                    return self.calculate_last_node_color(world, id);
-                }
-                else{
-                    let mut num_of_rays = node.child.len() as f64;
-                    if node.recursion_depth > 0{
-                        let denominator = (node.recursion_depth + 1) as f64 * SCATTERED_RAYS_FALLOFF;
-                        num_of_rays /= denominator;
-                    }
-                    let one_over_num_of_rays = 1. / num_of_rays;
-                    let mut result = self.calculate_last_node_color(world, id);
-                    for child in node.child.iter(){
-                        if let Some(child_node) = self.arena.get_node(NodeId::Parent(*child)){
-                            result += self.calculate_node_color(world, NodeId::Parent(child_node.id)) * child_node.ray.direction.distance() * one_over_num_of_rays;
-                        }
-                    }
-                    return result;
+            for tile in receiver{
+                self.write_tile(&tile);
+                if !on_tile(self){
+                    abort.store(true, Ordering::Relaxed);
+                    break;
                 }
             }
+        });
+    }
+
+    fn write_tile(&mut self, tile: &Tile)
+    {
+        let settings = &self.settings;
+        let start_x = settings.width_chunk * (tile.chunk_num % settings.chunks_per_row());
+        let start_y = settings.height_chunk * (tile.chunk_num / settings.chunks_per_row());
+        for (row, pixels) in tile.pixels.chunks(settings.width_chunk as usize).enumerate(){
+            let start = (start_x + (start_y + row as u32) * settings.width) as usize;
+            self.buffer[start..start + pixels.len()].copy_from_slice(pixels);
         }
-        Color::white()
+        self.chunk_num += 1;
+        self.total_num_of_rays += tile.num_of_rays;
     }
 
-    fn calculate_last_node_color(&self, world: &World, id: NodeId) -> Color{
+    // Returns the image in 0RGB format, one u32 per pixel
+    pub fn to_u32_buffer(&self) -> Vec<u32>
+    {
+        let tone_mapper = self.settings.tone_mapper();
+        self.buffer.iter().map(|pixel| tone_mapper.to_u32(pixel.color)).collect()
+    }
 
-        if let Some(node) = self.arena.get_node(id){
-            if let Some((item, collision_point)) = world.item_that_collide(&node.ray){
-                let normal = item.normal_at_point(&collision_point).unwrap().normalized();
+    // Format depends on the extension of the path, see output::save_image
+    pub fn save_image(&self, path: &str) -> image::ImageResult<()>
+    {
+        let colors: Vec<Color> = self.buffer.iter().map(|pixel| pixel.color).collect();
+        output::save_image(path, self.settings.width, self.settings.height, &colors, Some(&self.settings.tone_mapper()))
+    }
 
-                let mut resulting_color = Color::new();
-                for light in world.lights.iter(){
-                    let ray = Ray::new(&collision_point, &(light.position - collision_point));
-                    if let None = world.item_that_collide(&ray){
-                        let angle = ray.direction.normalized().dot(normal);
-                        if angle > 0.{
-                            resulting_color += (light.color * angle) * item.color() * item.reflectivity();
-                        }
-                    }
-                }
-                return resulting_color;
-            }
-        }
-        Color::new()
+    // Saves the pass next to the image, i.e. image_depth.png for image.png. Formats with floats
+    // get the raw values, 8 bit ones get them mapped to visible colors, see Aov::colors.
+    // Returns the path of the saved pass.
+    pub fn save_aov(&self, image_path: &str, aov: Aov) -> image::ImageResult<String>
+    {
+        let path = aov.path(image_path);
+        let raw = output::ImageFormat::from_path(&path).is_some_and(|format| format.is_hdr());
+        output::save_image(&path, self.settings.width, self.settings.height, &aov.colors(&self.buffer, raw), None)?;
+        Ok(path)
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use crate::camera::Camera;
+    use crate::settings::RenderSettings;
+    use crate::world::World;
+    use crate::shapes::Sphere;
+    use crate::vector::Vector;
+    use crate::material::Material;
+    use crate::lightsource::Lightsource;
+    use crate::aov::Aov;
+    use crate::filter::PixelFilter;
+    use crate::pixel::Color;
+    use std::sync::Arc;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn render_empty_world_headless()
+    {
+        let world = World::new();
+        let mut camera = Camera::new(&RenderSettings::new()).unwrap();
+        assert!(!camera.is_finished());
+
+        camera.render(&world, 4);
+        assert!(camera.is_finished());
+
+        let buffer = camera.to_u32_buffer();
+        assert_eq!(buffer.len(), 400 * 300);
+        // Every ray misses, so the whole image is the sky color
+        assert!(buffer.iter().all(|pixel| *pixel == 0x80DAEB));
+    }
+
+    #[test]
+    fn render_same_image_on_many_threads()
+    {
+        let mut world = World::new();
+        world.add_shape(Arc::new(Sphere{radius: 1.,
+                                       position: Vector{x: 0., y: 0., z: -5.},
+                                       material: Material::new_color_ref(200, 100, 50, 0.9, true)}));
+        world.add_light(Arc::new(Lightsource::new(&Vector{x: 10., y: -10., z: 10.}, 1.)));
+
+        let mut single_threaded = Camera::new(&RenderSettings::new()).unwrap();
+        single_threaded.render(&world, 1);
+        let mut multi_threaded = Camera::new(&RenderSettings::new()).unwrap();
+        multi_threaded.render(&world, 8);
+
+        assert!(multi_threaded.is_finished());
+        assert_eq!(single_threaded.total_num_of_rays, multi_threaded.total_num_of_rays);
+        assert_eq!(single_threaded.to_u32_buffer(), multi_threaded.to_u32_buffer());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn same_seed_same_image()
+    {
+        let mut world = World::new();
+        world.add_shape(Arc::new(Sphere{radius: 1.,
+                                       position: Vector{x: 0., y: 0., z: -5.},
+                                       material: Material::new_color_ref(200, 100, 50, 0.9, false)}));
+        world.add_light(Arc::new(Lightsource::new(&Vector{x: 10., y: -10., z: 10.}, 1.)));
+        let mut settings = RenderSettings::new();
+        settings.width = 40;
+        settings.height = 40;
+        settings.samples_per_pixel = 4;
+        settings.seed = 5;
+        let render = |settings: &RenderSettings, num_of_threads| {
+            let mut camera = Camera::new(settings).unwrap();
+            camera.render(&world, num_of_threads);
+            camera.buffer.iter().map(|pixel| pixel.color).collect::<Vec<Color>>()
+        };
+
+        // Bit identical radiance, whatever the threads and the chunks are
+        let image = render(&settings, 1);
+        assert_eq!(image, render(&settings, 7));
+        settings.width_chunk = 8;
+        settings.height_chunk = 5;
+        assert_eq!(image, render(&settings, 3));
+        settings.seed = 6;
+        assert_ne!(image, render(&settings, 3));
+    }
+
+    #[test]
+    fn render_aovs()
+    {
+        let mut world = World::new();
+        world.add_shape_with_material_id(Arc::new(Sphere{radius: 1.,
+                                                         position: Vector{x: 0., y: 0., z: -5.},
+                                                         material: Material::new_color_ref(200, 100, 50, 0.9, true)}), 7);
+        let mut settings = RenderSettings::new();
+        settings.num_of_reflected_rays = 4;
+        let mut camera = Camera::new(&settings).unwrap();
+        camera.render(&world, 2);
+
+        // Camera looks straight at the sphere through the middle of the image
+        let center = camera.buffer[(150 * 400 + 200) as usize];
+        // Half of the pixel away from the middle
+        assert_approx_eq!(center.depth, 9., 1e-3);
+        assert_approx_eq!(center.normal.z, 1., 1e-3);
+        assert_approx_eq!(center.albedo.r, 200. / 255.);
+        assert_eq!(center.object_id, 1);
+        assert_eq!(center.material_id, 7);
+        let corner = camera.buffer[0];
+        assert_eq!(corner.object_id, 0);
+        assert_eq!(corner.depth, 0.);
+
+        let directory = std::env::temp_dir().join(format!("nrtrt_aov_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let image_path = directory.join("render.pfm");
+        let path = camera.save_aov(image_path.to_str().unwrap(), Aov::Depth).unwrap();
+        assert!(path.ends_with("render_depth.pfm"));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), "PF\n400 300\n-1.0\n".len() as u64 + 400 * 300 * 3 * 4);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn antialiased_edges()
+    {
+        let mut world = World::new();
+        world.add_shape(Arc::new(Sphere{radius: 1.,
+                                       position: Vector{x: 0., y: 0., z: -5.},
+                                       material: Material::new_color_ref(255, 255, 255, 0.9, false)}));
+        let mut settings = RenderSettings::new();
+        settings.width = 40;
+        settings.height = 40;
+        settings.num_of_reflected_rays = 2;
+        settings.max_ray_depth = 1;
+        // Albedo is filtered like the color, so the pixels on the edge of the sphere get a part of it
+        let partly_covered = |settings: &RenderSettings| {
+            let mut camera = Camera::new(settings).unwrap();
+            camera.render(&world, 2);
+            camera.buffer.iter().filter(|pixel| pixel.albedo.r > 0.01 && pixel.albedo.r < 0.99).count()
+        };
+        assert_eq!(partly_covered(&settings), 0);
+        settings.samples_per_pixel = 16;
+        assert!(partly_covered(&settings) > 10);
+        settings.pixel_filter = PixelFilter::Mitchell;
+        assert!(partly_covered(&settings) > 10);
+    }
+
+    #[test]
+    fn stop_rendering_when_asked()
+    {
+        let world = World::new();
+        let mut camera = Camera::new(&RenderSettings::new()).unwrap();
+        let mut num_of_tiles = 0;
+        camera.render_with_progress(&world, 4, |_| {
+            num_of_tiles += 1;
+            num_of_tiles < 3
+        });
+        assert_eq!(num_of_tiles, 3);
+        assert!(!camera.is_finished());
+    }
+
+    #[test]
+    fn render_with_different_settings()
+    {
+        let world = World::new();
+        let mut preview_settings = RenderSettings::new();
+        preview_settings.width = 40;
+        preview_settings.height = 20;
+        preview_settings.num_of_reflected_rays = 10;
+        let mut preview = Camera::new(&preview_settings).unwrap();
+        let mut final_image = Camera::new(&RenderSettings::new()).unwrap();
+
+        preview.render(&world, 2);
+        final_image.render(&world, 2);
+        assert_eq!(preview.to_u32_buffer().len(), 40 * 20);
+        assert_eq!(final_image.to_u32_buffer().len(), 400 * 300);
+
+        preview_settings.width = 50;
+        assert!(Camera::new(&preview_settings).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+// Command line options of the nrtrt binary
+use crate::settings::{RenderSettings, FovAxis};
+use crate::tonemap::ToneMapping;
+use crate::output::ImageFormat;
+use crate::aov::Aov;
+use crate::view::Projection;
+use crate::filter::PixelFilter;
+use std::{error, fmt};
+
+pub const USAGE: &str = "Usage: nrtrt [OPTIONS] <SCENE>
+
+Renders the scene described in the SCENE file.
+
+Options:
+  -o, --output <PATH>        Where to save the image, the extension picks the format:
+                             png, jpg, ppm or hdr, pfm, exr for raw radiance [default: image.png]
+  -r, --resolution <WxH>     Size of the image, i.e. 800x600
+      --fov <DEGREES>        Horizontal field of view
+      --vfov <DEGREES>       Vertical field of view
+      --projection <NAME>    Projection of the camera: perspective, orthographic, fisheye or equirectangular
+      --spp <N>              Number of samples per pixel
+      --filter <NAME>        Pixel filter: box, tent, gaussian or mitchell
+  -s, --samples <N>          Number of rays scattered from every diffuse surface
+  -d, --depth <N>            Maximum number of bounces of every ray
+  -t, --threads <N>          Number of render threads [default: number of CPU cores]
+      --preview              Show the image in a window while it renders (default)
+      --no-preview           Render without any window
+      --seed <N>             Seed of the random numbers
+      --exposure <STOPS>     Brightness correction, +1 doubles the brightness
+      --tonemap <NAME>       Tone mapping: clamp, reinhard, extended-reinhard or aces
+      --aov <NAMES>          Also save these passes next to the image, comma separated:
+                             depth, normal, albedo, object, material or all
+  -h, --help                 Print this message
+
+Values given here override the ones from the scene file.";
+
+#[derive(Debug, PartialEq)]
+pub enum CliError
+{
+    // Help was requested, not really an error
+    Help,
+    Invalid(String)
+}
+
+impl fmt::Display for CliError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::Invalid(message) => write!(f, "{}\n\n{}", message, USAGE)
+        }
+    }
+}
+
+impl error::Error for CliError {}
+
+#[derive(Debug)]
+pub struct Options
+{
+    pub scene_path: String,
+    pub output_path: String,
+    pub resolution: Option<(u32, u32)>,
+    // Field of view and whether it is vertical
+    pub fov: Option<(f64, FovAxis)>,
+    pub samples: Option<usize>,
+    pub samples_per_pixel: Option<u32>,
+    pub pixel_filter: Option<PixelFilter>,
+    pub depth: Option<u32>,
+    pub threads: usize,
+    pub preview: bool,
+    pub seed: Option<u64>,
+    pub exposure: Option<f64>,
+    pub tone_mapping: Option<ToneMapping>,
+    pub projection: Option<Projection>,
+    // Passes saved together with the image
+    pub aovs: Vec<Aov>,
+}
+
+impl Options
+{
+    // Parses the arguments, without the name of the program
+    pub fn parse<I>(args: I) -> Result<Options, CliError>
+        where I: IntoIterator<Item = String>
+    {
+        let mut options = Options{
+            scene_path: String::new(),
+            output_path: "image.png".to_string(),
+            resolution: None,
+            fov: None,
+            samples: None,
+            samples_per_pixel: None,
+            pixel_filter: None,
+            depth: None,
+            threads: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            preview: true,
+            seed: None,
+            exposure: None,
+            tone_mapping: None,
+            projection: None,
+            aovs: Vec::new(),
+        };
+        let mut scene_path = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next()
+        {
+            match arg.as_str()
+            {
+                "-h" | "--help" => return Err(CliError::Help),
+                "-o" | "--output" => options.output_path = Options::value(&arg, args.next())?,
+                "-r" | "--resolution" => options.resolution = Some(Options::parse_resolution(&Options::value(&arg, args.next())?)?),
+                "--fov" => options.fov = Some((Options::number(&arg, args.next())?, FovAxis::Horizontal)),
+                "--vfov" => options.fov = Some((Options::number(&arg, args.next())?, FovAxis::Vertical)),
+                "--spp" => options.samples_per_pixel = Some(Options::number(&arg, args.next())?),
+                "--filter" => {
+                    let name = Options::value(&arg, args.next())?;
+                    match PixelFilter::from_name(&name)
+                    {
+                        Some(filter) => options.pixel_filter = Some(filter),
+                        None => return Err(CliError::Invalid(format!("unknown pixel filter '{}'", name)))
+                    }
+                },
+                "-s" | "--samples" => options.samples = Some(Options::number(&arg, args.next())?),
+                "-d" | "--depth" => options.depth = Some(Options::number(&arg, args.next())?),
+                "-t" | "--threads" => options.threads = Options::number(&arg, args.next())?,
+                "--preview" => options.preview = true,
+                "--no-preview" => options.preview = false,
+                "--seed" => options.seed = Some(Options::number(&arg, args.next())?),
+                "--exposure" => options.exposure = Some(Options::number(&arg, args.next())?),
+                "--tonemap" => {
+                    let name = Options::value(&arg, args.next())?;
+                    match ToneMapping::from_name(&name)
+                    {
+                        Some(tone_mapping) => options.tone_mapping = Some(tone_mapping),
+                        None => return Err(CliError::Invalid(format!("unknown tone mapping '{}'", name)))
+                    }
+                },
+                "--projection" => {
+                    let name = Options::value(&arg, args.next())?;
+                    match Projection::from_name(&name)
+                    {
+                        Some(projection) => options.projection = Some(projection),
+                        None => return Err(CliError::Invalid(format!("unknown projection '{}'", name)))
+                    }
+                },
+                "--aov" => {
+                    for name in Options::value(&arg, args.next())?.split(',')
+                    {
+                        let aovs = match name
+                        {
+                            "all" => Aov::ALL.to_vec(),
+                            _ => match Aov::from_name(name)
+                            {
+                                Some(aov) => vec![aov],
+                                None => return Err(CliError::Invalid(format!("unknown pass '{}'", name)))
+                            }
+                        };
+                        for aov in aovs
+                        {
+                            if !options.aovs.contains(&aov)
+                            {
+                                options.aovs.push(aov);
+                            }
+                        }
+                    }
+                },
+                _ if arg.starts_with('-') => return Err(CliError::Invalid(format!("unknown option '{}'", arg))),
+                _ => {
+                    if scene_path.is_some()
+                    {
+                        return Err(CliError::Invalid(format!("unexpected argument '{}', only one scene can be rendered", arg)));
+                    }
+                    scene_path = Some(arg);
+                }
+            }
+        }
+        options.scene_path = match scene_path
+        {
+            Some(scene_path) => scene_path,
+            None => return Err(CliError::Invalid("missing the scene file".to_string()))
+        };
+        if ImageFormat::from_path(&options.output_path).is_none()
+        {
+            return Err(CliError::Invalid(format!("unsupported image format of '{}'", options.output_path)));
+        }
+        if options.threads == 0
+        {
+            return Err(CliError::Invalid("at least one thread is needed".to_string()));
+        }
+        Ok(options)
+    }
+
+    fn value(option: &str, value: Option<String>) -> Result<String, CliError>
+    {
+        value.ok_or_else(|| CliError::Invalid(format!("option '{}' needs a value", option)))
+    }
+
+    fn number<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, CliError>
+    {
+        let value = Options::value(option, value)?;
+        value.parse::<T>().map_err(|_| CliError::Invalid(format!("invalid value '{}' of option '{}'", value, option)))
+    }
+
+    fn parse_resolution(value: &str) -> Result<(u32, u32), CliError>
+    {
+        let error = || CliError::Invalid(format!("invalid resolution '{}', expected WIDTHxHEIGHT", value));
+        let mut dimensions = value.splitn(2, 'x');
+        let width = dimensions.next().ok_or_else(error)?.parse::<u32>().map_err(|_| error())?;
+        let height = dimensions.next().ok_or_else(error)?.parse::<u32>().map_err(|_| error())?;
+        Ok((width, height))
+    }
+
+    // Overrides the settings (i.e. from the scene file) with the values given on the command line
+    pub fn apply(&self, settings: &mut RenderSettings)
+    {
+        if let Some((width, height)) = self.resolution
+        {
+            settings.width = width;
+            settings.height = height;
+        }
+        if let Some((fov, fov_axis)) = self.fov
+        {
+            settings.fov = fov;
+            settings.fov_axis = fov_axis;
+        }
+        if let Some(samples) = self.samples
+        {
+            settings.num_of_reflected_rays = samples;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel
+        {
+            settings.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(pixel_filter) = self.pixel_filter
+        {
+            settings.pixel_filter = pixel_filter;
+        }
+        if let Some(depth) = self.depth
+        {
+            settings.max_ray_depth = depth;
+        }
+        if let Some(seed) = self.seed
+        {
+            settings.seed = seed;
+        }
+        if let Some(exposure) = self.exposure
+        {
+            settings.exposure = exposure;
+        }
+        if let Some(tone_mapping) = self.tone_mapping
+        {
+            // Keep the white point from the scene
+            let same_operator = std::mem::discriminant(&tone_mapping) == std::mem::discriminant(&settings.tone_mapping);
+            if !same_operator
+            {
+                settings.tone_mapping = tone_mapping;
+            }
+        }
+        if let Some(projection) = self.projection
+        {
+            // Keep the extent from the scene
+            let same_projection = std::mem::discriminant(&projection) == std::mem::discriminant(&settings.projection);
+            if !same_projection
+            {
+                settings.projection = projection;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::cli::{Options, CliError};
+    use crate::settings::{RenderSettings, FovAxis};
+    use crate::tonemap::ToneMapping;
+    use crate::aov::Aov;
+    use crate::view::Projection;
+    use crate::filter::PixelFilter;
+
+    fn parse(args: &[&str]) -> Result<Options, CliError>
+    {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_options()
+    {
+        let options = parse(&["-o", "out.png", "scene.txt", "--resolution", "800x600", "-s", "50",
+                              "--depth", "2", "-t", "3", "--no-preview", "--seed", "7", "--fov", "90",
+                              "--exposure", "1.5", "--tonemap", "reinhard", "--spp", "16", "--filter", "tent"]).unwrap();
+        assert_eq!(options.scene_path, "scene.txt");
+        assert_eq!(options.output_path, "out.png");
+        assert_eq!(options.threads, 3);
+        assert!(!options.preview);
+        assert!(options.aovs.is_empty());
+
+        let mut settings = RenderSettings::new();
+        options.apply(&mut settings);
+        assert_eq!(settings.width, 800);
+        assert_eq!(settings.height, 600);
+        assert_eq!(settings.num_of_reflected_rays, 50);
+        assert_eq!(settings.samples_per_pixel, 16);
+        assert_eq!(settings.pixel_filter, PixelFilter::Tent);
+        assert_eq!(settings.max_ray_depth, 2);
+        assert_eq!(settings.seed, 7);
+        assert_eq!(settings.fov, 90.);
+        assert_eq!(settings.fov_axis, FovAxis::Horizontal);
+        assert_eq!(settings.exposure, 1.5);
+        assert_eq!(settings.tone_mapping, ToneMapping::Reinhard);
+    }
+
+    #[test]
+    fn defaults_keep_scene_settings()
+    {
+        let options = parse(&["scene.txt"]).unwrap();
+        assert_eq!(options.output_path, "image.png");
+        assert!(options.preview);
+        assert!(options.threads >= 1);
+
+        let mut settings = RenderSettings::new();
+        settings.width = 123;
+        options.apply(&mut settings);
+        assert_eq!(settings.width, 123);
+    }
+
+    #[test]
+    fn invalid_arguments()
+    {
+        assert_eq!(parse(&["--help"]).unwrap_err(), CliError::Help);
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["a.scene", "b.scene"]).is_err());
+        assert!(parse(&["scene.txt", "--depth"]).is_err());
+        assert!(parse(&["scene.txt", "--depth", "deep"]).is_err());
+        assert!(parse(&["scene.txt", "--resolution", "800"]).is_err());
+        assert!(parse(&["scene.txt", "--threads", "0"]).is_err());
+        assert!(parse(&["scene.txt", "--fast"]).is_err());
+        assert!(parse(&["scene.txt", "--tonemap", "filmic"]).is_err());
+        assert!(parse(&["scene.txt", "--filter", "lanczos"]).is_err());
+        assert!(parse(&["scene.txt", "--output", "image.gif"]).is_err());
+        assert!(parse(&["scene.txt", "--output", "image.exr"]).is_ok());
+        assert!(parse(&["scene.txt", "--aov", "depth,position"]).is_err());
+    }
+
+    #[test]
+    fn parse_aovs()
+    {
+        let options = parse(&["scene.txt", "--aov", "normal,depth", "--aov", "depth"]).unwrap();
+        assert_eq!(options.aovs, vec![Aov::Normal, Aov::Depth]);
+        let options = parse(&["scene.txt", "--aov", "all"]).unwrap();
+        assert_eq!(options.aovs, Aov::ALL.to_vec());
+    }
+
+    #[test]
+    fn vertical_fov()
+    {
+        let mut settings = RenderSettings::new();
+        parse(&["scene.txt", "--vfov", "30"]).unwrap().apply(&mut settings);
+        assert_eq!(settings.fov, 30.);
+        assert_eq!(settings.fov_axis, FovAxis::Vertical);
+    }
+
+    #[test]
+    fn projection()
+    {
+        let mut settings = RenderSettings::new();
+        settings.projection = Projection::Orthographic{extent: 3.};
+        parse(&["scene.txt", "--projection", "orthographic"]).unwrap().apply(&mut settings);
+        assert_eq!(settings.projection, Projection::Orthographic{extent: 3.});
+        parse(&["scene.txt", "--projection", "fisheye"]).unwrap().apply(&mut settings);
+        assert_eq!(settings.projection, Projection::Fisheye);
+        assert!(parse(&["scene.txt", "--projection", "cylindrical"]).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+// Reconstruction of the pixels from many samples: where in the pixel the samples go and how much
+// every one of them counts.
+use crate::sampler::Sampler;
+use std::fmt;
+
+// Pixel filters, all of them separable. Samples are spread over the whole filter, so the wider
+// ones reach into the neighbouring pixels and smooth the edges more.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFilter
+{
+    // Every sample inside the pixel counts the same
+    Box,
+    // Weight falls linearly to 0 at one pixel from the center
+    Tent,
+    // Gaussian (alpha 2) cut at 1.5 pixels
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3, sharper than the Gaussian, reaches 2 pixels
+    Mitchell,
+}
+
+impl PixelFilter
+{
+    // Names used in the scene files and on the command line
+    pub fn from_name(name: &str) -> Option<PixelFilter>
+    {
+        match name
+        {
+            "box" => Some(PixelFilter::Box),
+            "tent" => Some(PixelFilter::Tent),
+            "gaussian" => Some(PixelFilter::Gaussian),
+            "mitchell" => Some(PixelFilter::Mitchell),
+            _ => None
+        }
+    }
+
+    // Distance from the center of the pixel (in pixels) where the weight drops to 0
+    pub fn radius(self) -> f64
+    {
+        match self
+        {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent => 1.,
+            PixelFilter::Gaussian => 1.5,
+            PixelFilter::Mitchell => 2.,
+        }
+    }
+
+    // Weight of the sample that is offset by x and y pixels from the center of the pixel
+    pub fn weight(self, x: f64, y: f64) -> f64
+    {
+        self.weight_1d(x) * self.weight_1d(y)
+    }
+
+    fn weight_1d(self, x: f64) -> f64
+    {
+        let x = x.abs();
+        let radius = self.radius();
+        if x > radius
+        {
+            return 0.;
+        }
+        match self
+        {
+            PixelFilter::Box => 1.,
+            PixelFilter::Tent => 1. - x / radius,
+            PixelFilter::Gaussian => (-2. * x * x).exp() - (-2. * radius * radius).exp(),
+            PixelFilter::Mitchell => {
+                let (b, c) = (1. / 3., 1. / 3.);
+                let value = if x < 1.
+                {
+                    (12. - 9. * b - 6. * c) * x.powi(3) + (-18. + 12. * b + 6. * c) * x.powi(2) + (6. - 2. * b)
+                }
+                else
+                {
+                    (-b - 6. * c) * x.powi(3) + (6. * b + 30. * c) * x.powi(2) + (-12. * b - 48. * c) * x + (8. * b + 24. * c)
+                };
+                value / 6.
+            }
+        }
+    }
+}
+
+impl fmt::Display for PixelFilter
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            PixelFilter::Box => write!(f, "box"),
+            PixelFilter::Tent => write!(f, "tent"),
+            PixelFilter::Gaussian => write!(f, "gaussian"),
+            PixelFilter::Mitchell => write!(f, "mitchell"),
+        }
+    }
+}
+
+// Points of the unit square with stratified jitter: the square is split into a grid of cells and
+// every sample lands at a random place of its own cell, so the samples can't clump together.
+// When the number of samples is not a square, the grid can't be full, so the samples are spread
+// as N rooks instead: every column and every row of the n x n grid gets exactly one sample and
+// the rows are shuffled, so no part of the pixel is left out.
+pub fn stratified_samples<S: Sampler>(num_of_samples: u32, sampler: &mut S) -> Vec<(f64, f64)>
+{
+    let columns = (num_of_samples as f64).sqrt().round() as u32;
+    if columns * columns == num_of_samples
+    {
+        return (0..num_of_samples).map(|idx| {
+            let (column, row) = (idx % columns, idx / columns);
+            let (x, y) = sampler.next_2d();
+            ((column as f64 + x) / columns as f64, (row as f64 + y) / columns as f64)
+        }).collect();
+    }
+    let mut rows: Vec<u32> = (0..num_of_samples).collect();
+    for idx in (1..rows.len()).rev()
+    {
+        let other = ((sampler.next_1d() * (idx + 1) as f64) as usize).min(idx);
+        rows.swap(idx, other);
+    }
+    rows.iter().enumerate().map(|(column, row)| {
+        let (x, y) = sampler.next_2d();
+        ((column as f64 + x) / num_of_samples as f64, (*row as f64 + y) / num_of_samples as f64)
+    }).collect()
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::filter::{PixelFilter, stratified_samples};
+    use assert_approx_eq::assert_approx_eq;
+    use crate::sampler::RandomSampler;
+
+    #[test]
+    fn filter_weights()
+    {
+        let filters = [PixelFilter::Box, PixelFilter::Tent, PixelFilter::Gaussian, PixelFilter::Mitchell];
+        for filter in filters.iter()
+        {
+            assert_eq!(PixelFilter::from_name(&filter.to_string()), Some(*filter));
+            // Highest in the middle, nothing outside of the radius
+            assert!(filter.weight(0., 0.) >= filter.weight(0.3, 0.1));
+            assert_eq!(filter.weight(filter.radius() + 0.01, 0.), 0.);
+            assert_approx_eq!(filter.weight(0.2, -0.4), filter.weight(-0.2, 0.4));
+        }
+        assert_eq!(PixelFilter::Box.weight(0.49, 0.2), 1.);
+        assert_approx_eq!(PixelFilter::Tent.weight(0.5, 0.), 0.5);
+        assert_approx_eq!(PixelFilter::Gaussian.weight(1.5, 0.), 0.);
+        // Mitchell is 8/9 in the middle and has negative lobes
+        assert_approx_eq!(PixelFilter::Mitchell.weight(0., 0.), 64. / 81.);
+        assert!(PixelFilter::Mitchell.weight(1.5, 0.) < 0.);
+        assert_eq!(PixelFilter::from_name("lanczos"), None);
+    }
+
+    #[test]
+    fn stratified()
+    {
+        let mut sampler = RandomSampler::new(1);
+        let samples = stratified_samples(16, &mut sampler);
+        assert_eq!(samples.len(), 16);
+        // One sample in every cell of the 4x4 grid
+        for column in 0..4
+        {
+            for row in 0..4
+            {
+                let in_cell = samples.iter().filter(|(x, y)| (x * 4.) as u32 == column && (y * 4.) as u32 == row).count();
+                assert_eq!(in_cell, 1);
+            }
+        }
+        let samples = stratified_samples(5, &mut sampler);
+        assert_eq!(samples.len(), 5);
+        assert!(samples.iter().all(|(x, y)| (0. ..1.).contains(x) && (0. ..1.).contains(y)));
+        // One sample in every column and every row of the 5x5 grid
+        for cell in 0..5
+        {
+            assert_eq!(samples.iter().filter(|(x, _)| (x * 5.) as u32 == cell).count(), 1);
+            assert_eq!(samples.iter().filter(|(_, y)| (y * 5.) as u32 == cell).count(), 1);
+        }
+        assert!(stratified_samples(0, &mut sampler).is_empty());
+    }
+
+    #[test]
+    fn whole_pixel_is_sampled()
+    {
+        // Counts that are not squares still sample every quadrant of the pixel evenly
+        let mut sampler = RandomSampler::new(3);
+        for num_of_samples in [3, 5].iter()
+        {
+            let mut quadrants = [0; 4];
+            let num_of_pixels = 4000;
+            for _ in 0..num_of_pixels
+            {
+                for (x, y) in stratified_samples(*num_of_samples, &mut sampler)
+                {
+                    quadrants[(x >= 0.5) as usize + 2 * (y >= 0.5) as usize] += 1;
+                }
+            }
+            for count in quadrants.iter()
+            {
+                let share = *count as f64 / (num_of_pixels * num_of_samples) as f64;
+                assert_approx_eq!(share, 0.25, 0.01);
+            }
+        }
+    }
+}
@@ -0,0 +1,24 @@
+pub mod vector;
+pub mod aabb;
+pub mod bvh;
+pub mod pixel;
+pub mod tonemap;
+pub mod output;
+pub mod aov;
+pub mod ray;
+// mod shape;
+pub mod lightsource;
+pub mod world;
+pub mod view;
+pub mod filter;
+pub mod camera;
+pub mod shapes;
+pub mod material;
+pub mod sampler;
+pub mod raytree;
+pub mod tracer;
+pub mod preview;
+pub mod settings;
+pub mod scene;
+pub mod obj;
+pub mod cli;
@@ -1,24 +1,504 @@
 use crate::vector::Vector;
 use crate::pixel::Color;
+use crate::ray::Ray;
+use crate::sampler;
+use std::f64::consts;
+
+// Shape of the light. Points give sharp shadows, the other shapes are sampled at random points,
+// so the shadows get soft edges (penumbrae) that are wider for bigger lights.
+#[derive(Clone, Copy, Debug)]
+pub enum LightShape
+{
+    Point,
+    // Ball around the position of the light, shining from the whole surface
+    Sphere{radius: f64},
+    // Parallelogram centered at the position, shining only to the side of edge1 x edge2
+    Rectangle{edge1: Vector, edge2: Vector},
+    // Disk centered at the position, shining only to the side of the normal
+    Disk{normal: Vector, radius: f64},
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Lightsource {
     pub position: Vector,
+    // Radiant power (in watts), spread over the whole sphere around the light
     pub intensity: f64,
+    // Tint of the emitted light, multiplies the power
     pub color: Color,
+    pub shape: LightShape,
+}
+
+// Anything that lights up the scene. Lights are shared between the render threads, so they have to be Send + Sync.
+pub trait Light: Send + Sync
+{
+    // Light reaching the point from a random point of the light picked with the sample from the unit square.
+    // None when the light can't reach the point at all.
+    fn sample(&self, point: &Vector, sample: (f64, f64)) -> Option<LightSample>;
+
+    // Distance along the ray to the surface of the light, lights without a surface can't be hit
+    fn intersect(&self, _ray: &Ray) -> Option<f64>
+    {
+        None
+    }
+
+    // Radiance of the surface of the light seen along the direction
+    fn radiance(&self, _direction: &Vector) -> Color
+    {
+        Color::new()
+    }
+}
+
+// Light reaching the point from one random point of the light
+pub struct LightSample
+{
+    // Unit direction from the shaded point to the light
+    pub direction: Vector,
+    // Distance to the light in that direction, shadows are cast only by the shapes before it
+    pub distance: f64,
+    // Light arriving at a surface that faces the direction (the cosine on the side of the shaded point is not included)
+    pub irradiance: Color,
 }
 
 impl Lightsource{
-    pub(crate) fn new(position: &Vector, intensity: f64) -> Lightsource
+    pub fn new(position: &Vector, intensity: f64) -> Lightsource
+    {
+        Lightsource::new_colored(position, intensity, Color::white())
+    }
+
+    pub fn new_colored(position: &Vector, intensity: f64, color: Color) -> Lightsource
+    {
+        Lightsource::new_area(position, intensity, color, LightShape::Point)
+    }
+
+    // Panics if the intensity is negative
+    pub fn new_area(position: &Vector, intensity: f64, color: Color, shape: LightShape) -> Lightsource
     {
+        assert!(intensity >= 0., "intensity of the light cannot be negative");
         Lightsource{
-            position: position.clone(),
+            position: *position,
+            intensity,
+            color,
+            shape
+        }
+    }
+
+    // Light arriving at the point on a surface that faces the light: the power divided by the area
+    // of the sphere around the light, so it falls off with the square of the distance
+    pub fn irradiance(&self, point: &Vector) -> Color
+    {
+        let squared_distance = (self.position - *point).dot(self.position - *point);
+        self.color * (self.intensity / (4. * consts::PI * squared_distance))
+    }
+
+    // Area of the surface that emits the light, 0 for points
+    pub fn area(&self) -> f64
+    {
+        match self.shape
+        {
+            LightShape::Point => 0.,
+            LightShape::Sphere{radius} => 4. * consts::PI * radius * radius,
+            LightShape::Rectangle{edge1, edge2} => (edge1 * edge2).distance(),
+            LightShape::Disk{radius, ..} => consts::PI * radius * radius,
+        }
+    }
+
+    // Distance along the unit direction to the plane through the position of the light
+    fn plane_distance(to_start: &Vector, direction: &Vector, normal: &Vector) -> Option<f64>
+    {
+        let denominator = normal.dot(*direction);
+        if denominator.abs() < 1e-12
+        {
+            return None;
+        }
+        let distance = -normal.dot(*to_start) / denominator;
+        if distance > 0. {Some(distance)} else {None}
+    }
+
+
+    // Sample of a flat light picked uniformly over its area: the radiance turned into the light
+    // reaching the point, which falls with the square of the distance and the cosine on the light
+    fn sample_flat(&self, point: &Vector, on_light: &Vector, normal: &Vector) -> Option<LightSample>
+    {
+        let to_light = *on_light - *point;
+        let distance = to_light.distance();
+        let direction = to_light * (1. / distance);
+        let light_cosine = -direction.dot(*normal);
+        if light_cosine <= 0.
+        {
+            return None;
+        }
+        let irradiance = self.radiance(&direction) * (self.area() * light_cosine / (distance * distance));
+        Some(LightSample{direction, distance, irradiance})
+    }
+}
+
+impl Light for Lightsource
+{
+    // The power leaves the surface evenly in every direction of its side, so the radiance is the power / (pi * area)
+    fn radiance(&self, direction: &Vector) -> Color
+    {
+        let facing = match self.shape
+        {
+            LightShape::Point => return Color::new(),
+            LightShape::Sphere{..} => true,
+            LightShape::Rectangle{edge1, edge2} => direction.dot(edge1 * edge2) < 0.,
+            LightShape::Disk{normal, ..} => direction.dot(normal) < 0.,
+        };
+        if !facing
+        {
+            return Color::new();
+        }
+        self.color * (self.intensity / (consts::PI * self.area()))
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<f64>
+    {
+        if let LightShape::Point = self.shape
+        {
+            return None;
+        }
+        let length = ray.direction.distance();
+        let direction = ray.direction * (1. / length);
+        let to_start = ray.start_position - self.position;
+        match self.shape
+        {
+            LightShape::Point => unreachable!(),
+            LightShape::Sphere{radius} => {
+                let b = to_start.dot(direction);
+                let delta = b * b - (to_start.dot(to_start) - radius * radius);
+                if delta < 0.
+                {
+                    return None;
+                }
+                [-b - delta.sqrt(), -b + delta.sqrt()].iter().copied().find(|distance| *distance > 0.)
+            },
+            LightShape::Rectangle{edge1, edge2} => {
+                let normal = edge1 * edge2;
+                let distance = Lightsource::plane_distance(&to_start, &direction, &normal)?;
+                let offset = to_start + direction * distance;
+                // Coordinates of the hit along the edges, measured from the center
+                let along = |edge: Vector, other: Vector| (other * normal).dot(offset) / (other * normal).dot(edge);
+                if along(edge1, edge2).abs() <= 0.5 && along(edge2, edge1).abs() <= 0.5 {Some(distance)} else {None}
+            },
+            LightShape::Disk{normal, radius} => {
+                let distance = Lightsource::plane_distance(&to_start, &direction, &normal)?;
+                let offset = to_start + direction * distance;
+                if offset.dot(offset) <= radius * radius {Some(distance)} else {None}
+            },
+        }
+    }
+
+    // Points inside of the sphere and behind the one sided lights can't be reached
+    fn sample(&self, point: &Vector, sample: (f64, f64)) -> Option<LightSample>
+    {
+        match self.shape
+        {
+            LightShape::Point => {
+                let to_light = self.position - *point;
+                let distance = to_light.distance();
+                Some(LightSample{direction: to_light * (1. / distance), distance, irradiance: self.irradiance(point)})
+            },
+            LightShape::Sphere{radius} => {
+                // Only the cap of the sphere that is visible from the point is sampled, uniformly
+                // over the cone of directions that it covers
+                let to_center = self.position - *point;
+                let center_distance = to_center.distance();
+                if center_distance <= radius
+                {
+                    return None;
+                }
+                let axis = to_center * (1. / center_distance);
+                let sin_max_squared = (radius / center_distance).powi(2);
+                let cos_max = (1. - sin_max_squared).sqrt();
+                let cos = 1. - sample.0 * (1. - cos_max);
+                let sin = (1. - cos * cos).max(0.).sqrt();
+                let angle = 2. * consts::PI * sample.1;
+                let (tangent, bitangent) = sampler::orthonormal_basis(&axis);
+                let direction = tangent * (sin * angle.cos()) + bitangent * (sin * angle.sin()) + axis * cos;
+                let distance = center_distance * cos - (radius * radius - center_distance * center_distance * sin * sin).max(0.).sqrt();
+                // Radiance times the solid angle of the cone
+                let solid_angle = 2. * consts::PI * (1. - cos_max);
+                Some(LightSample{direction, distance, irradiance: self.radiance(&direction) * solid_angle})
+            },
+            LightShape::Rectangle{edge1, edge2} => {
+                let on_light = self.position + edge1 * (sample.0 - 0.5) + edge2 * (sample.1 - 0.5);
+                self.sample_flat(point, &on_light, &(edge1 * edge2).normalized())
+            },
+            LightShape::Disk{normal, radius} => {
+                let (x, y) = sampler::concentric_disk(sample);
+                let normal = normal.normalized();
+                let (tangent, bitangent) = sampler::orthonormal_basis(&normal);
+                let on_light = self.position + (tangent * x + bitangent * y) * radius;
+                self.sample_flat(point, &on_light, &normal)
+            },
+        }
+    }
+}
+
+// Light of a very distant source (i.e. the sun): parallel rays, the same light everywhere and no falloff.
+// The source covers a disk of the sky that is angular_diameter wide, bigger disks give softer shadows.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight
+{
+    // Unit direction the light travels in, from the source towards the scene
+    pub direction: Vector,
+    // Irradiance (in watts per square meter) of a surface that faces the light
+    pub intensity: f64,
+    pub color: Color,
+    // In radians, 0 is a point on the sky that casts sharp shadows
+    pub angular_diameter: f64,
+}
+
+impl DirectionalLight
+{
+    // The angular diameter is given in degrees, like the field of view. Panics if the intensity is negative.
+    pub fn new(direction: &Vector, intensity: f64, color: Color, angular_diameter: f64) -> DirectionalLight
+    {
+        assert!(intensity >= 0., "intensity of the light cannot be negative");
+        DirectionalLight{
+            direction: direction.normalized(),
             intensity,
-            color: Color{
-                r: 255,
-                g: 255,
-                b: 255
+            color,
+            angular_diameter: angular_diameter.to_radians()
+        }
+    }
+}
+
+impl Light for DirectionalLight
+{
+    // Random direction towards the disk of the source, uniform over its solid angle. The source is
+    // infinitely far away, so every shape in that direction casts the shadow.
+    fn sample(&self, _point: &Vector, sample: (f64, f64)) -> Option<LightSample>
+    {
+        let axis = self.direction * -1.;
+        let cos_max = (self.angular_diameter / 2.).cos();
+        let cos = 1. - sample.0 * (1. - cos_max);
+        let sin = (1. - cos * cos).max(0.).sqrt();
+        let angle = 2. * consts::PI * sample.1;
+        let (tangent, bitangent) = sampler::orthonormal_basis(&axis);
+        let direction = tangent * (sin * angle.cos()) + bitangent * (sin * angle.sin()) + axis * cos;
+        Some(LightSample{direction, distance: f64::INFINITY, irradiance: self.color * self.intensity})
+    }
+}
+
+// Point light that shines only into a cone around its direction. Inside of the inner cone it is as bright
+// as the point light with the same intensity, in the last falloff angle of the cone it fades out smoothly.
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight
+{
+    pub position: Vector,
+    // Unit axis of the cone
+    pub direction: Vector,
+    // Radiant power (in watts) of the point light that the spot light cuts the cone out of
+    pub intensity: f64,
+    pub color: Color,
+    // Half of the angle at the apex of the cone, in radians
+    pub cone_angle: f64,
+    // Width of the soft edge inside the cone, in radians, 0 gives a sharp edge
+    pub falloff: f64,
+}
+
+impl SpotLight
+{
+    // Angles are given in degrees, the falloff can't be wider than the cone. Panics if the intensity is negative.
+    pub fn new(position: &Vector, direction: &Vector, intensity: f64, color: Color, cone_angle: f64, falloff: f64) -> SpotLight
+    {
+        assert!(intensity >= 0., "intensity of the light cannot be negative");
+        SpotLight{
+            position: *position,
+            direction: direction.normalized(),
+            intensity,
+            color,
+            cone_angle: cone_angle.to_radians(),
+            falloff: falloff.min(cone_angle).to_radians()
+        }
+    }
+
+    // How much of the light goes in the direction: 1 in the inner cone, 0 outside of the cone
+    // and smoothly in between
+    pub fn cone_factor(&self, direction: &Vector) -> f64
+    {
+        let cos = direction.normalized().dot(self.direction);
+        let (cos_outer, cos_inner) = (self.cone_angle.cos(), (self.cone_angle - self.falloff).cos());
+        if cos <= cos_outer
+        {
+            return 0.;
+        }
+        if cos >= cos_inner
+        {
+            return 1.;
+        }
+        let x = (cos - cos_outer) / (cos_inner - cos_outer);
+        x * x * (3. - 2. * x)
+    }
+}
+
+impl Light for SpotLight
+{
+    fn sample(&self, point: &Vector, _sample: (f64, f64)) -> Option<LightSample>
+    {
+        let to_light = self.position - *point;
+        let distance = to_light.distance();
+        let factor = self.cone_factor(&(to_light * -1.));
+        if factor == 0.
+        {
+            return None;
+        }
+        let irradiance = self.color * (factor * self.intensity / (4. * consts::PI * distance * distance));
+        Some(LightSample{direction: to_light * (1. / distance), distance, irradiance})
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::lightsource::{Light, Lightsource, LightShape, DirectionalLight, SpotLight};
+    use crate::vector::Vector;
+    use crate::pixel::Color;
+    use crate::ray::Ray;
+    use crate::sampler::{Sampler, RandomSampler};
+    use assert_approx_eq::assert_approx_eq;
+    use std::f64::consts;
+
+    #[test]
+    fn inverse_square_falloff()
+    {
+        let light = Lightsource::new(&Vector{x: 0., y: -2., z: 0.}, 100.);
+        let near = light.irradiance(&Vector{x: 0., y: 0., z: 0.});
+        assert_approx_eq!(near.r, 100. / (4. * consts::PI * 4.));
+        assert_eq!(near.r, near.b);
+        let far = light.irradiance(&Vector{x: 0., y: 2., z: 0.});
+        assert_approx_eq!(far.g, near.g / 4.);
+    }
+
+    #[test]
+    fn colored_light()
+    {
+        let light = Lightsource::new_colored(&Vector{x: 1., y: 0., z: 0.}, 4. * consts::PI, Color{r: 1., g: 0.5, b: 0.});
+        let irradiance = light.irradiance(&Vector::new());
+        assert_approx_eq!(irradiance.r, 1.);
+        assert_approx_eq!(irradiance.g, 0.5);
+        assert_eq!(irradiance.b, 0.);
+    }
+
+    // Average of many samples of the light arriving at the point from the direction of the normal
+    fn sampled_irradiance(light: &Lightsource, point: &Vector, normal: &Vector) -> f64
+    {
+        let mut sampler = RandomSampler::new(3);
+        let num_of_samples = 20000;
+        let mut sum = 0.;
+        for _ in 0..num_of_samples
+        {
+            if let Some(sample) = light.sample(point, sampler.next_2d())
+            {
+                assert_approx_eq!(sample.direction.distance(), 1.);
+                // The sampled point is on the surface of the light
+                assert_approx_eq!(light.intersect(&Ray::new(point, &sample.direction)).unwrap(), sample.distance, 1e-6);
+                sum += sample.irradiance.r * sample.direction.dot(*normal).max(0.);
             }
         }
+        sum / num_of_samples as f64
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn small_lights_look_like_points()
+    {
+        let point = Vector{x: 0., y: 0., z: 0.};
+        let normal = Vector{x: 0., y: 0., z: 1.};
+        let position = Vector{x: 0., y: 0., z: 20.};
+        let expected = Lightsource::new(&position, 1000.).irradiance(&point).r;
+        let sphere = Lightsource::new_area(&position, 1000., Color::white(), LightShape::Sphere{radius: 0.5});
+        assert_approx_eq!(sampled_irradiance(&sphere, &point, &normal), expected, expected * 0.01);
+        // One sided lights send the whole power to their side, that is twice as much as the point does
+        let rectangle = Lightsource::new_area(&position, 1000., Color::white(),
+                                              LightShape::Rectangle{edge1: Vector{x: 0., y: 0.5, z: 0.}, edge2: Vector{x: 0.5, y: 0., z: 0.}});
+        let rectangle_irradiance = sampled_irradiance(&rectangle, &point, &normal);
+        let disk = Lightsource::new_area(&position, 1000., Color::white(), LightShape::Disk{normal: Vector{x: 0., y: 0., z: -1.}, radius: 0.3});
+        let disk_irradiance = sampled_irradiance(&disk, &point, &normal);
+        // Power / pi / distance^2 straight below the light, instead of the point's power / (4 pi) / distance^2
+        assert_approx_eq!(rectangle_irradiance, 4. * expected, expected * 0.01);
+        assert_approx_eq!(disk_irradiance, 4. * expected, expected * 0.01);
+    }
+
+    #[test]
+    fn one_sided_lights()
+    {
+        let disk = Lightsource::new_area(&Vector::new(), 10., Color::white(), LightShape::Disk{normal: Vector{x: 0., y: 1., z: 0.}, radius: 1.});
+        assert!(disk.sample(&Vector{x: 0., y: -3., z: 0.}, (0.3, 0.6)).is_none());
+        assert!(disk.sample(&Vector{x: 0., y: 3., z: 0.}, (0.3, 0.6)).is_some());
+        let down = Vector{x: 0., y: -1., z: 0.};
+        assert_approx_eq!(disk.radiance(&down).g, 10. / (consts::PI * consts::PI));
+        assert_eq!(disk.radiance(&(down * -1.)), Color::new());
+        assert!(Lightsource::new(&Vector::new(), 10.).intersect(&Ray::new(&Vector{x: 0., y: 3., z: 0.}, &down)).is_none());
+    }
+
+    #[test]
+    fn sun()
+    {
+        let direction = Vector{x: 0., y: 1., z: -1.};
+        let sharp = DirectionalLight::new(&direction, 2., Color{r: 1., g: 0.5, b: 0.5}, 0.);
+        // Same light everywhere, coming from the opposite of the direction
+        for point in [Vector::new(), Vector{x: 100., y: -50., z: 3.}].iter()
+        {
+            let sample = sharp.sample(point, (0.3, 0.8)).unwrap();
+            assert_approx_eq!(sample.direction.dot(direction.normalized()), -1.);
+            assert_eq!(sample.distance, f64::INFINITY);
+            assert_approx_eq!(sample.irradiance.r, 2.);
+            assert_approx_eq!(sample.irradiance.g, 1.);
+        }
+        assert!(sharp.intersect(&Ray::new(&Vector::new(), &(direction * -1.))).is_none());
+
+        // Directions of the sun with a diameter spread up to its radius
+        let soft = DirectionalLight::new(&direction, 2., Color::white(), 10.);
+        let mut sampler = RandomSampler::new(1);
+        let angles: Vec<f64> = (0..1000).map(|_| {
+            let sample = soft.sample(&Vector::new(), sampler.next_2d()).unwrap();
+            (-sample.direction.dot(soft.direction)).min(1.).acos().to_degrees()
+        }).collect();
+        assert!(angles.iter().all(|angle| *angle <= 5. + 1e-9));
+        assert!(angles.iter().any(|angle| *angle > 4.));
+    }
+
+    #[test]
+    fn spot()
+    {
+        let spot = SpotLight::new(&Vector{x: 0., y: -4., z: 0.}, &Vector{x: 0., y: 2., z: 0.}, 100., Color::white(), 30., 10.);
+        // Straight below it shines like the point light
+        let below = spot.sample(&Vector::new(), (0.5, 0.5)).unwrap();
+        assert_approx_eq!(below.irradiance.r, Lightsource::new(&spot.position, 100.).irradiance(&Vector::new()).r);
+        assert_approx_eq!(below.distance, 4.);
+        // Soft edge between 20 and 30 degrees from the axis, nothing outside of the cone
+        let at_angle = |degrees: f64| spot.cone_factor(&Vector{x: degrees.to_radians().sin(), y: degrees.to_radians().cos(), z: 0.});
+        assert_eq!(at_angle(19.), 1.);
+        assert_approx_eq!(at_angle(25.), 0.5, 0.1);
+        assert!(at_angle(22.) > at_angle(28.));
+        assert_eq!(at_angle(31.), 0.);
+        assert!(spot.sample(&Vector{x: 0., y: -8., z: 0.}, (0.5, 0.5)).is_none());
+        assert!(spot.sample(&Vector{x: 4., y: 0., z: 0.}, (0.5, 0.5)).is_none());
+    }
+
+    #[test]
+    fn hitting_lights()
+    {
+        let down = Vector{x: 0., y: -2., z: 0.};
+        let start = Vector{x: 0.2, y: 5., z: 0.3};
+        let sphere = Lightsource::new_area(&Vector::new(), 1., Color::white(), LightShape::Sphere{radius: 1.});
+        assert_approx_eq!(sphere.intersect(&Ray::new(&Vector{x: 0., y: 5., z: 0.}, &down)).unwrap(), 4.);
+        assert!(sphere.intersect(&Ray::new(&Vector{x: 2., y: 5., z: 0.}, &down)).is_none());
+        let rectangle = Lightsource::new_area(&Vector::new(), 1., Color::white(),
+                                              LightShape::Rectangle{edge1: Vector{x: 1., y: 0., z: 0.}, edge2: Vector{x: 0., y: 0., z: 1.}});
+        assert_approx_eq!(rectangle.intersect(&Ray::new(&start, &down)).unwrap(), 5.);
+        assert!(rectangle.intersect(&Ray::new(&Vector{x: 0.6, y: 5., z: 0.}, &down)).is_none());
+        assert!(rectangle.intersect(&Ray::new(&start, &(down * -1.))).is_none());
+        let disk = Lightsource::new_area(&Vector::new(), 1., Color::white(), LightShape::Disk{normal: Vector{x: 0., y: 1., z: 0.}, radius: 0.5});
+        assert_approx_eq!(disk.intersect(&Ray::new(&start, &down)).unwrap(), 5.);
+        assert!(disk.intersect(&Ray::new(&Vector{x: 0.4, y: 5., z: 0.4}, &down)).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn reject_negative_intensity()
+    {
+        DirectionalLight::new(&Vector{x: 0., y: 1., z: 0.}, -1., Color::white(), 0.5);
+    }
+}
@@ -1,98 +1,72 @@
-mod vector;
-mod pixel;
-mod ray;
-// mod shape;
-mod lightsource;
-mod world;
-mod camera;
-mod shapes;
-mod material;
-mod lambertian;
-mod raytree;
-
-use vector::Vector;
-use lightsource::Lightsource;
-use world::World;
-use camera::{Camera, UpdateStatus};
-use material::Material;
-use std::rc::Rc;
+use nrtrt::cli::{Options, CliError};
+use nrtrt::camera::Camera;
+use nrtrt::scene::Scene;
+use nrtrt::preview::{Preview, UpdateStatus};
+use std::process;
 
 fn main() {
-    println!("Hello, world!");
-    let mut world = World::new();
-    world.add_shape(Rc::new(shapes::Sphere{radius: 3.5,
-                                           position: Vector{x: -3.,
-                                                            y: 1.5,
-                                                            z: -12.},
-                                           material: Material::new_color_ref(50, 255, 10, 0.6, false)}));
-
-    world.add_shape(Rc::new(shapes::Sphere{radius: 2.,
-                                           position: Vector{x: -1.2,
-                                                            y: -4.,
-                                                            z: -8.5},
-                                           material: Material::new_color_ref(150, 80, 220, 0.95, true)}));
-
-    world.add_shape(Rc::new(shapes::Sphere{radius: 2.5,
-                                           position: Vector{x: 3.2,
-                                                            y: 2.,
-                                                            z: -9.},
-                                           material: Material::new_color_ref(220, 220, 20, 0.9, true)}));
-
-    world.add_shape(Rc::new(shapes::Sphere{radius: 1.5,
-                                           position: Vector{x: 3.2,
-                                                            y: -2.,
-                                                            z: -9.},
-                                           material: Material::new_color_ref(230, 5, 10, 0.9, false)}));
-
-    world.add_shape(Rc::new(shapes::Rectangle::new(&Vector{x: 0.,
-                                                         y: 3.8,
-                                                         z: 0.},
-                                                  &(20., 20.,),
-                                                  &Material::new_color_ref(155, 105, 40, 1., true))));
-
-    // world.add_light(Rc::new(Lightsource::new(&Vector{x: 30.,
-    //                                                  y: 0.,
-    //                                                  z: -10.},
-    //                 0.2)));
-
-    // world.add_light(Rc::new(Lightsource::new(&Vector{x: 10000.,
-    //                                                  y: 0.,
-    //                                                  z: 0.},
-    //                 0.3)));
-
-    // world.add_light(Rc::new(Lightsource::new(&Vector{x: -10000.,
-    //                                                  y: -10000.,
-    //                                                  z: 0.},
-    //                 0.6)));
+    let options = match Options::parse(std::env::args().skip(1)){
+        Ok(options) => options,
+        Err(CliError::Help) => {
+            println!("{}", CliError::Help);
+            return;
+        },
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(2);
+        }
+    };
+    let mut scene = match Scene::load(&options.scene_path){
+        Ok(scene) => scene,
+        Err(error) => {
+            eprintln!("{}: {}", options.scene_path, error);
+            process::exit(1);
+        }
+    };
+    for warning in scene.warnings.iter(){
+        eprintln!("Warning: {}", warning);
+    }
+    options.apply(&mut scene.settings);
+    scene.world.build_bvh();
+    let world = &scene.world;
 
-    world.add_light(Rc::new(Lightsource::new(&Vector{x: 10000.,
-        y: -10000.,
-        z: 10000.},
-                                             0.4)));
+    let mut camera = match scene.camera(){
+        Ok(camera) => camera,
+        Err(error) => {
+            eprintln!("Invalid render settings: {}", error);
+            process::exit(1);
+        }
+    };
+    // Without a display the whole image is rendered and saved without opening a window
+    if !options.preview{
+        camera.render(world, options.threads);
+        println!("Total ray shot count: {}", camera.total_num_of_rays);
+        save_image(&camera, &options);
+        return;
+    }
 
-    world.add_light(Rc::new(Lightsource::new(&Vector{x: 10000.,
-        y: -1000.,
-        z: 10000.},
-                                             0.4)));
+    let mut preview = Preview::new(camera.settings().width, camera.settings().height);
+    camera.render_with_progress(world, options.threads, |camera| {
+        println!("Total ray shot count: {}", camera.total_num_of_rays);
+        preview.update(camera) != UpdateStatus::AboutToExit
+    });
+    if camera.is_finished(){
+        save_image(&camera, &options);
+    }
+    // Keep showing the image until the window is closed
+    while preview.update(&camera) != UpdateStatus::AboutToExit{}
+}
 
-    let mut camera = Camera::new();
-    let mut status = UpdateStatus::NotFinished;
-    let mut saved = false;
-    while status != UpdateStatus::AboutToExit
-    {
-        status = camera.update();
-        match status{
-            UpdateStatus::NotFinished =>{
-                camera.shoot_primary_rays(&world);
-                println!("Total ray shot count: {}", camera.total_num_of_rays);
-            },
-            UpdateStatus::Finished =>{
-                if saved == false{
-                    saved = true;
-                    camera.save_image();
-                }
-            },
-            UpdateStatus::AboutToExit => break
+fn save_image(camera: &Camera, options: &Options){
+    let path = &options.output_path;
+    if let Err(error) = camera.save_image(path){
+        eprintln!("Cannot save the image to {}: {}", path, error);
+        process::exit(1);
+    }
+    for aov in options.aovs.iter(){
+        if let Err(error) = camera.save_aov(path, *aov){
+            eprintln!("Cannot save the {} pass of {}: {}", aov.name(), path, error);
+            process::exit(1);
         }
     }
 }
@@ -5,7 +5,11 @@ pub struct Material
 {
     pub reflectivity: f64,
     pub color: Color,
-    pub is_specular: bool
+    pub is_specular: bool,
+    // Transparent materials (i.e. glass) refract the light, opaque ones have None
+    pub refractive_index: Option<f64>,
+    // Radiance that the surface sends out by itself (i.e. neon signs and light panels), black for most of them
+    pub emission: Color
 }
 
 impl Material
@@ -16,44 +20,85 @@ impl Material
         Material{
             reflectivity,
             color,
-            is_specular
+            is_specular,
+            refractive_index: None,
+            emission: Color::new()
         }
     }
 
     #[allow(dead_code)]
-    pub fn default() -> Material
+    pub fn new_color(r: u8, g: u8, b: u8) -> Material
     {
         Material{
             reflectivity: 0.8,
-            color: Color::white(),
-            is_specular: true
+            color: Color::from_u8(r, g, b),
+            is_specular: true,
+            refractive_index: None,
+            emission: Color::new()
         }
     }
 
-    #[allow(dead_code)]
-    pub fn new_color(r: u8, g: u8, b: u8) -> Material
+    pub fn new_color_ref(r: u8, g: u8, b: u8, refl: f64, is_specular: bool) -> Material
     {
         Material{
-            reflectivity: 0.8,
-            color: Color{
-                r,
-                g,
-                b
-            },
-            is_specular: true
+            reflectivity: refl,
+            color: Color::from_u8(r, g, b),
+            is_specular,
+            refractive_index: None,
+            emission: Color::new()
         }
     }
 
-    pub fn new_color_ref(r: u8, g: u8, b: u8, refl: f64, is_specular: bool) -> Material
+    // Clear dielectric, i.e. glass has refractive index 1.5 and water 1.33. The color tints the refracted light.
+    pub fn new_glass(refractive_index: f64, color: Color) -> Material
     {
         Material{
-            reflectivity: refl,
-            color: Color{
-                r,
-                g,
-                b
-            },
-            is_specular
+            reflectivity: 1.,
+            color,
+            is_specular: true,
+            refractive_index: Some(refractive_index),
+            emission: Color::new()
         }
     }
-}
\ No newline at end of file
+}
+
+// Grey, mostly reflective mirror
+impl Default for Material
+{
+    fn default() -> Material
+    {
+        Material{
+            reflectivity: 0.8,
+            color: Color::white(),
+            is_specular: true,
+            refractive_index: None,
+            emission: Color::new()
+        }
+    }
+}
+
+// Part of the light that is reflected (the rest is refracted) on the border between the air and
+// a dielectric with given refractive index, by Schlick's approximation. Cosine is of the angle
+// between the normal and the ray on the air side of the border.
+pub fn fresnel_reflectance(refractive_index: f64, cosine: f64) -> f64
+{
+    let r0 = ((1. - refractive_index) / (1. + refractive_index)).powi(2);
+    r0 + (1. - r0) * (1. - cosine).powi(5)
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::material::fresnel_reflectance;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn fresnel()
+    {
+        // Glass reflects 4% of the light that hits it straight on
+        assert_approx_eq!(fresnel_reflectance(1.5, 1.), 0.04);
+        // And everything at grazing angles
+        assert_approx_eq!(fresnel_reflectance(1.5, 0.), 1.);
+        assert!(fresnel_reflectance(1.5, 0.5) < fresnel_reflectance(1.5, 0.2));
+    }
+}
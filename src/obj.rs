@@ -0,0 +1,630 @@
+// Loader of Wavefront OBJ models and their MTL material libraries.
+//
+// Supported OBJ directives: v, vn, vt, f, g, o, mtllib and usemtl. Polygons are split into triangle
+// fans, so they should be convex. Every group (or object) with a material becomes a separate mesh,
+// all meshes of the model share the vertex buffers.
+// Supported MTL directives: newmtl, Kd, Ks, Ns, Ni, Tf, Ke (emission) and illum.
+// Anything else is not rendered and reported as a warning, once per directive and file.
+use crate::material::Material;
+use crate::pixel::Color;
+use crate::shapes::{MeshBuffers, MeshTriangle, TriangleMesh};
+use crate::vector::Vector;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{error, fmt, fs, io};
+
+#[derive(Debug)]
+pub enum ObjError
+{
+    Io(PathBuf, io::Error),
+    // Malformed input, line numbers start from 1
+    Parse{path: PathBuf, line: usize, message: String}
+}
+
+impl fmt::Display for ObjError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            ObjError::Io(path, error) => write!(f, "cannot read {}: {}", path.display(), error),
+            ObjError::Parse{path, line, message} => write!(f, "{}:{}: {}", path.display(), line, message)
+        }
+    }
+}
+
+impl error::Error for ObjError {}
+
+// Faces of one group that use the same material
+#[derive(Debug)]
+pub struct ObjGroup
+{
+    pub name: String,
+    pub material: Option<String>,
+    pub triangles: Vec<MeshTriangle>
+}
+
+pub struct ObjModel
+{
+    pub buffers: MeshBuffers,
+    pub groups: Vec<ObjGroup>,
+    pub materials: HashMap<String, Material>,
+    // Directives and references that were skipped, i.e. "model.obj:12: unsupported directive 'l' (3 times)"
+    pub warnings: Vec<String>
+}
+
+// Material of faces without any (or with an unknown) material: grey diffuse surface, Kd defaults to 0.8 in MTL
+fn default_material() -> Material
+{
+    Material::new(1., Color{r: 0.8, g: 0.8, b: 0.8}, false)
+}
+
+// Collects the unsupported directives of one file, so every one of them is reported only once
+struct Unsupported
+{
+    // Directive, line of its first use and number of uses, in order of the first use
+    directives: Vec<(String, usize, usize)>
+}
+
+impl Unsupported
+{
+    fn new() -> Unsupported
+    {
+        Unsupported{directives: Vec::new()}
+    }
+
+    fn add(&mut self, directive: &str, line: usize)
+    {
+        match self.directives.iter_mut().find(|(name, _, _)| name == directive)
+        {
+            Some((_, _, count)) => *count += 1,
+            None => self.directives.push((directive.to_string(), line, 1))
+        }
+    }
+
+    fn report(self, path: &Path, warnings: &mut Vec<String>)
+    {
+        for (directive, line, count) in self.directives
+        {
+            let times = if count > 1 {format!(" ({} times)", count)} else {String::new()};
+            warnings.push(format!("{}:{}: unsupported directive '{}'{}", path.display(), line, directive, times));
+        }
+    }
+}
+
+struct Parser<'a>
+{
+    path: &'a Path,
+    line: usize
+}
+
+impl<'a> Parser<'a>
+{
+    fn error(&self, message: String) -> ObjError
+    {
+        ObjError::Parse{path: self.path.to_path_buf(), line: self.line, message}
+    }
+
+    fn numbers(&self, directive: &str, values: &[&str], min: usize, max: usize) -> Result<Vec<f64>, ObjError>
+    {
+        if values.len() < min || values.len() > max
+        {
+            return Err(self.error(format!("'{}' needs {} to {} numbers, found {}", directive, min, max, values.len())));
+        }
+        values.iter()
+            .map(|value| match value.parse::<f64>()
+            {
+                Ok(number) if number.is_finite() => Ok(number),
+                _ => Err(self.error(format!("'{}' is not a finite number", value)))
+            })
+            .collect()
+    }
+
+    fn color(&self, directive: &str, values: &[&str]) -> Result<Color, ObjError>
+    {
+        let channels = self.numbers(directive, values, 1, 3)?;
+        // A single value means grey
+        let channel = |idx: usize| channels[idx.min(channels.len() - 1)];
+        Ok(Color{
+            r: channel(0).clamp(0., 1.),
+            g: channel(1).clamp(0., 1.),
+            b: channel(2).clamp(0., 1.)
+        })
+    }
+
+    // Like the color, but the values are not limited to 1 (i.e. the emitted light)
+    fn radiance(&self, directive: &str, values: &[&str]) -> Result<Color, ObjError>
+    {
+        let channels = self.numbers(directive, values, 1, 3)?;
+        let channel = |idx: usize| channels[idx.min(channels.len() - 1)].max(0.);
+        Ok(Color{r: channel(0), g: channel(1), b: channel(2)})
+    }
+
+    // Converts the 1-based (or negative, relative to the end) index of the element to 0-based one
+    fn index(&self, value: &str, len: usize, kind: &str) -> Result<usize, ObjError>
+    {
+        let index = value.parse::<i64>().map_err(|_| self.error(format!("invalid {} index '{}'", kind, value)))?;
+        let resolved = if index < 0 {len as i64 + index} else {index - 1};
+        if index == 0 || resolved < 0 || resolved >= len as i64
+        {
+            return Err(self.error(format!("{} index {} is out of range, there are {} of them", kind, index, len)));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+// Parsed material description, mapped to the Material once the whole entry is known
+struct MtlEntry
+{
+    diffuse: Color,
+    specular: Option<Color>,
+    shininess: f64,
+    refractive_index: f64,
+    transmission: Color,
+    emission: Color,
+    illumination: Option<u32>
+}
+
+impl MtlEntry
+{
+    fn new() -> MtlEntry
+    {
+        MtlEntry{
+            diffuse: default_material().color,
+            specular: None,
+            shininess: 0.,
+            refractive_index: 1.5,
+            transmission: Color::white(),
+            emission: Color::new(),
+            illumination: None
+        }
+    }
+
+    // Illumination models with refraction (6 and 7) make glass with index of refraction Ni, tinted by Tf.
+    // Other surfaces become mirrors when the illumination model enables ray traced reflections (3 and above),
+    // or when the highlight is both sharp and strong. Mirrors reflect Ks times the diffuse color,
+    // diffuse surfaces keep all the light of Kd.
+    fn to_material(&self) -> Material
+    {
+        let mut material = if let Some(6) | Some(7) = self.illumination
+        {
+            Material::new_glass(self.refractive_index, self.transmission)
+        }
+        else
+        {
+            let specular = self.specular.map_or(0., |color| color.max_channel());
+            let is_specular = match self.illumination
+            {
+                Some(illumination) if illumination >= 3 => specular > 0.,
+                _ => specular >= 0.5 && self.shininess >= 100.
+            };
+            Material::new(if is_specular {specular} else {1.}, self.diffuse, is_specular)
+        };
+        material.emission = self.emission;
+        material
+    }
+}
+
+pub fn parse_mtl(text: &str, path: &Path, warnings: &mut Vec<String>) -> Result<HashMap<String, Material>, ObjError>
+{
+    let mut parser = Parser{path, line: 0};
+    let mut unsupported = Unsupported::new();
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlEntry)> = None;
+    for (idx, line) in text.lines().enumerate()
+    {
+        parser.line = idx + 1;
+        let tokens: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+        let (directive, values) = match tokens.split_first()
+        {
+            Some((directive, values)) => (*directive, values),
+            None => continue
+        };
+        if directive == "newmtl"
+        {
+            if values.len() != 1
+            {
+                return Err(parser.error("'newmtl' needs a single name".to_string()));
+            }
+            if let Some((name, entry)) = current.take()
+            {
+                materials.insert(name, entry.to_material());
+            }
+            current = Some((values[0].to_string(), MtlEntry::new()));
+            continue;
+        }
+        let entry = match current.as_mut()
+        {
+            Some((_, entry)) => entry,
+            None => return Err(parser.error(format!("'{}' before any 'newmtl'", directive)))
+        };
+        match directive
+        {
+            "Kd" => entry.diffuse = parser.color(directive, values)?,
+            "Ks" => entry.specular = Some(parser.color(directive, values)?),
+            "Ns" => entry.shininess = parser.numbers(directive, values, 1, 1)?[0],
+            "Ni" => entry.refractive_index = parser.numbers(directive, values, 1, 1)?[0],
+            "Tf" => entry.transmission = parser.color(directive, values)?,
+            "Ke" => entry.emission = parser.radiance(directive, values)?,
+            "illum" => entry.illumination = Some(parser.numbers(directive, values, 1, 1)?[0] as u32),
+            _ => unsupported.add(directive, parser.line)
+        }
+    }
+    if let Some((name, entry)) = current
+    {
+        materials.insert(name, entry.to_material());
+    }
+    unsupported.report(path, warnings);
+    Ok(materials)
+}
+
+impl ObjModel
+{
+    // Loads the model and the material libraries it uses (paths are relative to the model)
+    pub fn load(path: &Path) -> Result<ObjModel, ObjError>
+    {
+        let text = fs::read_to_string(path).map_err(|error| ObjError::Io(path.to_path_buf(), error))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        ObjModel::parse(&text, path, |library| {
+            let library_path = directory.join(library);
+            let text = fs::read_to_string(&library_path).map_err(|error| ObjError::Io(library_path.clone(), error))?;
+            Ok((text, library_path))
+        })
+    }
+
+    // Parses the model, `read_library` gives the content and path of a material library with given name.
+    // The path is used only in messages.
+    pub fn parse<F>(text: &str, path: &Path, mut read_library: F) -> Result<ObjModel, ObjError>
+        where F: FnMut(&str) -> Result<(String, PathBuf), ObjError>
+    {
+        let mut model = ObjModel{
+            buffers: MeshBuffers::default(),
+            groups: Vec::new(),
+            materials: HashMap::new(),
+            warnings: Vec::new()
+        };
+        let mut parser = Parser{path, line: 0};
+        let mut unsupported = Unsupported::new();
+        let mut group_name = "default".to_string();
+        let mut material: Option<String> = None;
+        // Index of the group that gets the faces, created with the first face
+        let mut current_group: Option<usize> = None;
+        let mut unknown_materials: Vec<String> = Vec::new();
+
+        for (idx, line) in text.lines().enumerate()
+        {
+            parser.line = idx + 1;
+            let tokens: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            let (directive, values) = match tokens.split_first()
+            {
+                Some((directive, values)) => (*directive, values),
+                None => continue
+            };
+            match directive
+            {
+                "v" => {
+                    // Optional w coordinate is ignored
+                    let coordinates = parser.numbers(directive, values, 3, 4)?;
+                    model.buffers.positions.push(Vector{x: coordinates[0], y: coordinates[1], z: coordinates[2]});
+                },
+                "vn" => {
+                    let coordinates = parser.numbers(directive, values, 3, 3)?;
+                    let normal = Vector{x: coordinates[0], y: coordinates[1], z: coordinates[2]};
+                    if normal.distance() == 0.
+                    {
+                        return Err(parser.error("normal cannot be a zero vector".to_string()));
+                    }
+                    model.buffers.normals.push(normal.normalized());
+                },
+                "vt" => {
+                    // Optional w coordinate is ignored
+                    let coordinates = parser.numbers(directive, values, 1, 3)?;
+                    model.buffers.uvs.push((coordinates[0], coordinates.get(1).copied().unwrap_or(0.)));
+                },
+                "f" => {
+                    let group = match current_group
+                    {
+                        Some(group) => group,
+                        None => {
+                            let group = model.find_or_add_group(&group_name, &material);
+                            current_group = Some(group);
+                            group
+                        }
+                    };
+                    let triangles = model.parse_face(&parser, values)?;
+                    model.groups[group].triangles.extend(triangles);
+                },
+                "g" | "o" => {
+                    group_name = if values.is_empty() {"default".to_string()} else {values.join(" ")};
+                    current_group = None;
+                },
+                "usemtl" => {
+                    if values.len() != 1
+                    {
+                        return Err(parser.error("'usemtl' needs a single name".to_string()));
+                    }
+                    material = Some(values[0].to_string());
+                    current_group = None;
+                },
+                "mtllib" => {
+                    if values.is_empty()
+                    {
+                        return Err(parser.error("'mtllib' needs a file name".to_string()));
+                    }
+                    for library in values
+                    {
+                        let (text, library_path) = read_library(library)?;
+                        let materials = parse_mtl(&text, &library_path, &mut model.warnings)?;
+                        model.materials.extend(materials);
+                    }
+                },
+                _ => unsupported.add(directive, parser.line)
+            }
+        }
+        unsupported.report(path, &mut model.warnings);
+        model.groups.retain(|group| !group.triangles.is_empty());
+        for group in model.groups.iter()
+        {
+            if let Some(name) = &group.material
+            {
+                if !model.materials.contains_key(name) && !unknown_materials.contains(name)
+                {
+                    unknown_materials.push(name.clone());
+                }
+            }
+        }
+        for name in unknown_materials
+        {
+            model.warnings.push(format!("{}: unknown material '{}', using the default one", path.display(), name));
+        }
+        Ok(model)
+    }
+
+    // Groups that were started again later, i.e. "g a ... g b ... g a", are merged
+    fn find_or_add_group(&mut self, name: &str, material: &Option<String>) -> usize
+    {
+        if let Some(idx) = self.groups.iter().position(|group| group.name == name && group.material == *material)
+        {
+            return idx;
+        }
+        self.groups.push(ObjGroup{
+            name: name.to_string(),
+            material: material.clone(),
+            triangles: Vec::new()
+        });
+        self.groups.len() - 1
+    }
+
+    // Splits the polygon (i.e. "f 1/1/1 2/2/1 3/3/1 4/4/1") into a triangle fan
+    fn parse_face(&self, parser: &Parser, values: &[&str]) -> Result<Vec<MeshTriangle>, ObjError>
+    {
+        if values.len() < 3
+        {
+            return Err(parser.error(format!("face needs at least 3 vertices, found {}", values.len())));
+        }
+        let mut positions = Vec::with_capacity(values.len());
+        let mut normals = Vec::with_capacity(values.len());
+        let mut uvs = Vec::with_capacity(values.len());
+        for vertex in values
+        {
+            let mut indices = vertex.split('/');
+            let position = indices.next().unwrap_or("");
+            positions.push(parser.index(position, self.buffers.positions.len(), "vertex")?);
+            match indices.next()
+            {
+                Some(uv) if !uv.is_empty() => uvs.push(parser.index(uv, self.buffers.uvs.len(), "texture coordinate")?),
+                _ => ()
+            }
+            match indices.next()
+            {
+                Some(normal) if !normal.is_empty() => normals.push(parser.index(normal, self.buffers.normals.len(), "normal")?),
+                _ => ()
+            }
+            if indices.next().is_some()
+            {
+                return Err(parser.error(format!("invalid face vertex '{}'", vertex)));
+            }
+        }
+        // Attributes are used only if every vertex has them
+        let normals = if normals.len() == positions.len() {Some(normals)} else {None};
+        let uvs = if uvs.len() == positions.len() {Some(uvs)} else {None};
+        let triangles = (1..positions.len() - 1).map(|idx| {
+            let corners = [0, idx, idx + 1];
+            MeshTriangle{
+                positions: corners.map(|corner| positions[corner]),
+                normals: normals.as_ref().map(|normals| corners.map(|corner| normals[corner])),
+                uvs: uvs.as_ref().map(|uvs| corners.map(|corner| uvs[corner]))
+            }
+        }).collect();
+        Ok(triangles)
+    }
+
+    // Scales the model and moves it by the offset
+    pub fn transform(&mut self, scale: f64, offset: &Vector)
+    {
+        for position in self.buffers.positions.iter_mut()
+        {
+            *position = *position * scale + *offset;
+        }
+        if scale < 0.
+        {
+            for normal in self.buffers.normals.iter_mut()
+            {
+                *normal = *normal * -1.;
+            }
+        }
+    }
+
+    // One mesh per group, all of them share the vertex buffers. The material overrides the ones from MTL files.
+    pub fn meshes(&self, material: Option<&Material>) -> Vec<TriangleMesh>
+    {
+        let buffers = Arc::new(self.buffers.clone());
+        self.groups.iter().map(|group| {
+            let group_material = match material
+            {
+                Some(material) => *material,
+                None => group.material.as_ref()
+                    .and_then(|name| self.materials.get(name))
+                    .copied()
+                    .unwrap_or_else(default_material)
+            };
+            TriangleMesh::new(Arc::clone(&buffers), group.triangles.clone(), &group_material)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::obj::{ObjModel, ObjError};
+    use crate::shapes::{Collision, MaterialTrait, MeshTriangle};
+    use crate::ray::Ray;
+    use crate::vector::Vector;
+    use crate::pixel::Color;
+    use assert_approx_eq::assert_approx_eq;
+    use std::path::{Path, PathBuf};
+
+    const MTL: &str = "
+        # Materials of the test model
+        newmtl red
+        Kd 1 0 0
+        Ks 0.1 0.1 0.1
+        Ns 10
+        map_Kd red.png
+
+        newmtl mirror
+        Kd 0.9 0.9 0.9
+        Ks 0.8 0.8 0.8
+        illum 3
+
+        newmtl glass
+        Kd 0 0 0
+        Ni 1.33
+        Tf 1 1 0.5
+        illum 7
+
+        newmtl neon
+        Kd 1 0.2 0.6
+        Ke 5 1 3
+    ";
+
+    fn parse(text: &str) -> Result<ObjModel, ObjError>
+    {
+        ObjModel::parse(text, Path::new("model.obj"), |name| {
+            match name
+            {
+                "materials.mtl" => Ok((MTL.to_string(), PathBuf::from(name))),
+                _ => Err(ObjError::Io(PathBuf::from(name), std::io::Error::new(std::io::ErrorKind::NotFound, "not found")))
+            }
+        })
+    }
+
+    #[test]
+    fn parse_model()
+    {
+        let model = parse("
+            mtllib materials.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            v 0 0 1
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            vn 0 0 2
+            g floor
+            usemtl red
+            s 1
+            f 1/1/1 2/2/1 3/3/1 4/4/1
+            g wall
+            usemtl mirror
+            f 1//1 -1//1 4//1
+            f 1 2 5
+            l 1 2
+            l 2 3
+        ").unwrap();
+        assert_eq!(model.buffers.positions.len(), 5);
+        assert_approx_eq!(model.buffers.normals[0].z, 1.);
+        assert_eq!(model.groups.len(), 2);
+
+        let floor = &model.groups[0];
+        assert_eq!(floor.name, "floor");
+        assert_eq!(floor.material.as_deref(), Some("red"));
+        assert_eq!(floor.triangles, vec![MeshTriangle{positions: [0, 1, 2], normals: Some([0, 0, 0]), uvs: Some([0, 1, 2])},
+                                         MeshTriangle{positions: [0, 2, 3], normals: Some([0, 0, 0]), uvs: Some([0, 2, 3])}]);
+        let wall = &model.groups[1];
+        assert_eq!(wall.triangles, vec![MeshTriangle{positions: [0, 4, 3], normals: Some([0, 0, 0]), uvs: None},
+                                        MeshTriangle{positions: [0, 1, 4], normals: None, uvs: None}]);
+
+        let red = model.materials["red"];
+        assert_approx_eq!(red.color.r, 1.);
+        assert_approx_eq!(red.color.g, 0.);
+        assert!(!red.is_specular);
+        let mirror = model.materials["mirror"];
+        assert!(mirror.is_specular);
+        assert_approx_eq!(mirror.reflectivity, 0.8, 0.01);
+        assert!(mirror.refractive_index.is_none());
+        let glass = model.materials["glass"];
+        assert_eq!(glass.refractive_index, Some(1.33));
+        assert_approx_eq!(glass.color.b, 0.5);
+        assert_eq!(glass.emission, Color::new());
+        // Emitted light is not limited to 1
+        let neon = model.materials["neon"];
+        assert_approx_eq!(neon.emission.r, 5.);
+        assert_approx_eq!(neon.emission.b, 3.);
+        assert_eq!(red.emission, Color::new());
+
+        assert_eq!(model.warnings.len(), 3);
+        assert!(model.warnings.iter().any(|warning| warning.contains("'map_Kd'")));
+        assert!(model.warnings.iter().any(|warning| warning.contains("'s'")));
+        assert!(model.warnings.iter().any(|warning| warning.contains("'l' (2 times)")));
+    }
+
+    #[test]
+    fn meshes_share_buffers()
+    {
+        let mut model = parse("
+            mtllib materials.mtl
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            usemtl red
+            f 1 2 3
+            usemtl unknown
+            f 3 2 1
+        ").unwrap();
+        model.transform(2., &Vector{x: 0., y: 0., z: -5.});
+        let meshes = model.meshes(None);
+        assert_eq!(meshes.len(), 2);
+        assert!(std::sync::Arc::ptr_eq(&meshes[0].buffers, &meshes[1].buffers));
+        assert_approx_eq!(meshes[0].color().g, 0.);
+        assert_approx_eq!(meshes[1].color().g, 0.8);
+        assert!(model.warnings.iter().any(|warning| warning.contains("unknown material 'unknown'")));
+
+        let ray = Ray::new(&Vector{x: 0.5, y: 0.5, z: 0.}, &Vector{x: 0., y: 0., z: -1.});
+        let point = meshes[0].collision_point(&ray).unwrap();
+        assert_approx_eq!(point.z, -5.);
+    }
+
+    #[test]
+    fn invalid_models()
+    {
+        let error_line = |text: &str| match parse(text)
+        {
+            Err(ObjError::Parse{line, ..}) => line,
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("model should not be parsed")
+        };
+        assert_eq!(error_line("v 0 0 0\nv 1 0 0\nf 1 2 3"), 3);
+        assert_eq!(error_line("v 0 0 0\nv 1 0 0\nf 1 2"), 3);
+        assert_eq!(error_line("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 0 1 2"), 4);
+        assert_eq!(error_line("v 0 0"), 1);
+        assert_eq!(error_line("vn 0 0 0"), 1);
+        assert_eq!(error_line("v 0 0 0\nv nan 0 0"), 2);
+        assert_eq!(error_line("v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 inf\nf 1 2 3"), 4);
+        assert!(matches!(parse("mtllib missing.mtl"), Err(ObjError::Io(..))));
+    }
+}
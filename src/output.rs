@@ -0,0 +1,262 @@
+// Saving of the rendered images. The format is picked from the extension of the file:
+// PNG, JPEG and PPM get tone mapped 8 bit colors, Radiance HDR, PFM and OpenEXR get the raw
+// linear radiance (without exposure and tone mapping), i.e. for compositing.
+use crate::pixel::Color;
+use crate::tonemap::ToneMapper;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat
+{
+    Png,
+    Jpeg,
+    Ppm,
+    // Radiance RGBE
+    Hdr,
+    // Portable float map
+    Pfm,
+    // OpenEXR with 32 bit float channels, uncompressed
+    Exr,
+}
+
+impl ImageFormat
+{
+    pub fn from_path(path: &str) -> Option<ImageFormat>
+    {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str()
+        {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "ppm" => Some(ImageFormat::Ppm),
+            "hdr" => Some(ImageFormat::Hdr),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None
+        }
+    }
+
+    pub fn is_hdr(self) -> bool
+    {
+        match self
+        {
+            ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Ppm => false,
+            ImageFormat::Hdr | ImageFormat::Pfm | ImageFormat::Exr => true
+        }
+    }
+}
+
+// Colors are given row by row, starting from the top left corner. Without the tone mapper the colors
+// are saved to 8 bit formats as they are (clamped to 0..1), i.e. for data that is already encoded.
+pub fn save_image(path: &str, width: u32, height: u32, colors: &[Color], tone_mapper: Option<&ToneMapper>) -> image::ImageResult<()>
+{
+    assert_eq!(colors.len(), (width * height) as usize);
+    let format = match ImageFormat::from_path(path)
+    {
+        Some(format) => format,
+        None => return Err(image::ImageError::UnsupportedError(format!("unknown image format of {}", path)))
+    };
+    if !format.is_hdr()
+    {
+        let buffer: Vec<u8> = colors.iter().flat_map(|color| match tone_mapper
+        {
+            Some(tone_mapper) => tone_mapper.to_rgb8(*color),
+            None => color.to_rgb8()
+        }).collect();
+        return image::save_buffer(path, &buffer, width, height, image::RGB(8)).map_err(image::ImageError::IoError);
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format
+    {
+        ImageFormat::Hdr => {
+            // Shared exponent of RGBE cannot store negative values
+            let pixels: Vec<image::Rgb<f32>> = colors.iter().map(|color| {
+                let [r, g, b] = channels(color);
+                image::Rgb([r.max(0.), g.max(0.), b.max(0.)])
+            }).collect();
+            image::hdr::HDREncoder::new(&mut writer).encode(&pixels, width as usize, height as usize)?;
+        },
+        ImageFormat::Pfm => write_pfm(&mut writer, width, height, colors)?,
+        ImageFormat::Exr => write_exr(&mut writer, width, height, colors)?,
+        _ => unreachable!()
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Channels as 32 bit floats, invalid values (NaN and infinity) become 0
+fn channels(color: &Color) -> [f32; 3]
+{
+    let channel = |value: f64| if value.is_finite() {value as f32} else {0.};
+    [channel(color.r), channel(color.g), channel(color.b)]
+}
+
+// Header, scale -1 means little endian. Rows go from the bottom to the top.
+pub fn write_pfm<W: Write>(writer: &mut W, width: u32, height: u32, colors: &[Color]) -> io::Result<()>
+{
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in colors.chunks(width as usize).rev()
+    {
+        for color in row
+        {
+            for channel in channels(color).iter()
+            {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Single part scanline file, one row per chunk, channels stored alphabetically (B, G, R)
+pub fn write_exr<W: Write>(writer: &mut W, width: u32, height: u32, colors: &[Color]) -> io::Result<()>
+{
+    const FLOAT: i32 = 2;
+    let mut header = Vec::new();
+    header.extend_from_slice(&0x0131_2f76u32.to_le_bytes());
+    // Version 2, no flags
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for name in ["B", "G", "R"].iter()
+    {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&FLOAT.to_le_bytes());
+        // Perceptually linear flag and reserved bytes
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        // Sampling in x and y
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    let mut window = Vec::new();
+    for value in [0i32, 0, width as i32 - 1, height as i32 - 1].iter()
+    {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    let attributes: [(&str, &str, Vec<u8>); 8] = [
+        ("channels", "chlist", channel_list),
+        ("compression", "compression", vec![0]),
+        ("dataWindow", "box2i", window.clone()),
+        ("displayWindow", "box2i", window),
+        ("lineOrder", "lineOrder", vec![0]),
+        ("pixelAspectRatio", "float", 1f32.to_le_bytes().to_vec()),
+        ("screenWindowCenter", "v2f", [0f32.to_le_bytes(), 0f32.to_le_bytes()].concat()),
+        ("screenWindowWidth", "float", 1f32.to_le_bytes().to_vec()),
+    ];
+    for (name, kind, value) in attributes.iter()
+    {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    }
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // Table with the position of every row in the file
+    let row_size = width as u64 * 3 * 4;
+    let first_row = header.len() as u64 + height as u64 * 8;
+    for y in 0..height as u64
+    {
+        writer.write_all(&(first_row + y * (8 + row_size)).to_le_bytes())?;
+    }
+    for (y, row) in colors.chunks(width as usize).enumerate()
+    {
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&(row_size as i32).to_le_bytes())?;
+        let row_channels: Vec<[f32; 3]> = row.iter().map(channels).collect();
+        for channel in [2, 1, 0].iter()
+        {
+            for pixel in row_channels.iter()
+            {
+                writer.write_all(&pixel[*channel].to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::output::{ImageFormat, write_pfm, write_exr};
+    use crate::pixel::Color;
+
+    fn read_f32(data: &[u8], offset: usize) -> f32
+    {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&data[offset..offset + 4]);
+        f32::from_le_bytes(bytes)
+    }
+
+    // 2x2 image with radiance above 1 in the top left corner
+    fn get_colors() -> Vec<Color>
+    {
+        vec![Color{r: 4., g: 2., b: 1.}, Color::white(),
+             Color::new(), Color{r: 0.25, g: 0.5, b: 0.75}]
+    }
+
+    #[test]
+    fn format_from_extension()
+    {
+        assert_eq!(ImageFormat::from_path("image.png"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path("out/render.JPG"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_path("render.exr"), Some(ImageFormat::Exr));
+        assert!(ImageFormat::from_path("render.exr").unwrap().is_hdr());
+        assert!(!ImageFormat::from_path("render.ppm").unwrap().is_hdr());
+        assert_eq!(ImageFormat::from_path("render.gif"), None);
+        assert_eq!(ImageFormat::from_path("render"), None);
+    }
+
+    #[test]
+    fn pfm()
+    {
+        let mut data = Vec::new();
+        write_pfm(&mut data, 2, 2, &get_colors()).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&data[..header.len()], header);
+        assert_eq!(data.len(), header.len() + 2 * 2 * 3 * 4);
+        // Bottom row goes first
+        assert_eq!(read_f32(&data, header.len() + 3 * 4), 0.25);
+        // Values above 1 are kept
+        assert_eq!(read_f32(&data, header.len() + 2 * 3 * 4), 4.);
+
+        // So are the negative ones (i.e. normals), only invalid values are lost
+        let mut data = Vec::new();
+        write_pfm(&mut data, 1, 1, &[Color{r: -0.5, g: f64::NAN, b: f64::INFINITY}]).unwrap();
+        assert_eq!(read_f32(&data, header.len()), -0.5);
+        assert_eq!(read_f32(&data, header.len() + 4), 0.);
+        assert_eq!(read_f32(&data, header.len() + 8), 0.);
+    }
+
+    #[test]
+    fn exr()
+    {
+        let mut data = Vec::new();
+        write_exr(&mut data, 2, 2, &get_colors()).unwrap();
+        assert_eq!(&data[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        let row_size = 4 + 4 + 2 * 3 * 4;
+        let header_size = data.len() - 2 * row_size - 2 * 8;
+        assert_eq!(data[header_size - 1], 0);
+
+        // The offset table points at the rows
+        let mut offset = [0; 8];
+        offset.copy_from_slice(&data[header_size + 8..header_size + 16]);
+        let second_row = u64::from_le_bytes(offset) as usize;
+        assert_eq!(second_row, header_size + 2 * 8 + row_size);
+        assert_eq!(&data[second_row..second_row + 4], &1i32.to_le_bytes());
+
+        // Blue channel of the first row, then green and red
+        let first_row = header_size + 2 * 8 + 8;
+        assert_eq!(read_f32(&data, first_row), 1.);
+        assert_eq!(read_f32(&data, first_row + 2 * 4), 2.);
+        assert_eq!(read_f32(&data, first_row + 4 * 4), 4.);
+        assert_eq!(read_f32(&data, first_row + 5 * 4), 1.);
+    }
+}
@@ -1,11 +1,23 @@
+use crate::tonemap::srgb_to_linear;
+use crate::vector::Vector;
 use std::ops;
 
-#[derive(Clone, Copy, Debug)]
+// Linear radiance, channels are not limited to 0..1 range. Values are converted to 8 bits
+// only when the image is shown or saved.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color
 {
-    pub r: u8,  //RED
-    pub g: u8,  //GREEN
-    pub b: u8,  //BLUE
+    pub r: f64,  //RED
+    pub g: f64,  //GREEN
+    pub b: f64,  //BLUE
+}
+
+impl Default for Color
+{
+    fn default() -> Color
+    {
+        Color::new()
+    }
 }
 
 impl Color
@@ -14,28 +26,61 @@ impl Color
     pub fn new() -> Color
     {
         Color {
-            r: 0,
-            g: 0,
-            b: 0,
+            r: 0.,
+            g: 0.,
+            b: 0.,
         }
     }
 
     pub fn white() -> Color
-    {        
+    {
+        Color {
+            r: 1.,
+            g: 1.,
+            b: 1.,
+        }
+    }
+
+    // Color from 8 bit channels, 255 is 1.0
+    pub fn from_u8(r: u8, g: u8, b: u8) -> Color
+    {
+        Color {
+            r: r as f64 / 255.,
+            g: g as f64 / 255.,
+            b: b as f64 / 255.,
+        }
+    }
+
+    // Color from 8 bit sRGB encoded channels (i.e. picked in an image editor), converted to linear values
+    pub fn from_srgb8(r: u8, g: u8, b: u8) -> Color
+    {
         Color {
-            r: 255,
-            g: 255,
-            b: 255,
-        } 
+            r: srgb_to_linear(r as f64 / 255.),
+            g: srgb_to_linear(g as f64 / 255.),
+            b: srgb_to_linear(b as f64 / 255.),
+        }
     }
 
-    pub fn to_u32(&self) -> u32
+    // Channels clamped to 0..1 and quantised to 8 bits
+    pub fn to_rgb8(self) -> [u8; 3]
     {
-        let r = self.r as u32 * 256 * 256;
-        let g = self.g as u32 * 256;
-        let b = self.b as u32;
+        let quantise = |channel: f64| (channel.clamp(0., 1.) * 255.).round() as u8;
+        [quantise(self.r), quantise(self.g), quantise(self.b)]
+    }
+
+    pub fn to_u32(self) -> u32
+    {
+        let [r, g, b] = self.to_rgb8();
+        let r = r as u32 * 256 * 256;
+        let g = g as u32 * 256;
+        let b = b as u32;
         r + g + b
     }
+
+    pub fn max_channel(&self) -> f64
+    {
+        self.r.max(self.g).max(self.b)
+    }
 }
 
 impl ops::Add<Color> for Color
@@ -44,9 +89,9 @@ impl ops::Add<Color> for Color
     fn add(self, rhs: Color) -> Color
     {
         Color {
-            r: (self.r as u16 + rhs.r as u16).min(255) as u8,
-            g: (self.g as u16 + rhs.g as u16).min(255) as u8,
-            b: (self.b as u16 + rhs.b as u16).min(255) as u8,
+            r: self.r + rhs.r,
+            g: self.g + rhs.g,
+            b: self.b + rhs.b,
         }
     }
 }
@@ -55,9 +100,9 @@ impl ops::AddAssign<Color> for Color
 {
     fn add_assign(&mut self, rhs: Color)
     {
-        self.r = (self.r as u16 + rhs.r as u16).min(255) as u8;
-        self.g = (self.g as u16 + rhs.g as u16).min(255) as u8;
-        self.b = (self.b as u16 + rhs.b as u16).min(255) as u8;
+        self.r += rhs.r;
+        self.g += rhs.g;
+        self.b += rhs.b;
     }
 }
 
@@ -67,9 +112,9 @@ impl ops::Mul<f64> for Color
     fn mul(self, rhs: f64) -> Color
     {
         Color {
-            r: ((self.r as f64) * rhs).min(255.) as u8,
-            g: ((self.g as f64) * rhs).min(255.) as u8,
-            b: ((self.b as f64) * rhs).min(255.) as u8,
+            r: self.r * rhs,
+            g: self.g * rhs,
+            b: self.b * rhs,
         }
     }
 }
@@ -80,9 +125,9 @@ impl ops::Mul<Color> for Color
     fn mul(self, rhs: Color) -> Color
     {
         Color {
-            r: (((self.r as f64 / 255.) * (rhs.r as f64 / 255.)) * 255.) as u8,
-            g: (((self.g as f64 / 255.) * (rhs.g as f64 / 255.)) * 255.) as u8,
-            b: (((self.b as f64 / 255.) * (rhs.b as f64 / 255.)) * 255.) as u8,
+            r: self.r * rhs.r,
+            g: self.g * rhs.g,
+            b: self.b * rhs.b,
         }
     }
 }
@@ -91,7 +136,26 @@ impl ops::Mul<Color> for Color
 pub struct Pixel
 {
     //Color of the pixel
-    pub color: Color
+    pub color: Color,
+    // Everything below describes the first hit of the camera ray (see aov::Aov)
+    // and is 0 when the ray hits nothing.
+    // Distance from the camera
+    pub depth: f64,
+    // Shading normal, turned towards the camera
+    pub normal: Vector,
+    // Color of the material
+    pub albedo: Color,
+    // Index of the shape in the world + 1
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl Default for Pixel
+{
+    fn default() -> Pixel
+    {
+        Pixel::new()
+    }
 }
 
 impl Pixel
@@ -99,7 +163,12 @@ impl Pixel
     pub fn new() -> Pixel
     {
         Pixel{
-            color: Color::new()
+            color: Color::new(),
+            depth: 0.,
+            normal: Vector::new(),
+            albedo: Color::new(),
+            object_id: 0,
+            material_id: 0,
         }
     }
 }
@@ -107,18 +176,33 @@ impl Pixel
 mod test
 {
     use crate::pixel::Color;
+    use assert_approx_eq::assert_approx_eq;
 
     #[test]
     fn to_u32()
     {
-        let mut pixel = Color::new();
+        let pixel = Color::new();
         assert_eq!(0, pixel.to_u32());
 
-        pixel.r = 23;
-        pixel.g = 242;
-        pixel.b = 65;
+        let pixel = Color::from_u8(23, 242, 65);
         //--------------------------PADDING--_---R----_---G----_---B----
         let converted_pixel: u32 = 0b00000000_00010111_11110010_01000001;
         assert_eq!(converted_pixel, pixel.to_u32());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn channels_are_not_clamped()
+    {
+        let light = Color{r: 2., g: 0.5, b: 0.};
+        let sum = light + light * 0.5 + Color::white();
+        assert_approx_eq!(sum.r, 4.);
+        assert_approx_eq!(sum.g, 1.75);
+        assert_approx_eq!(sum.b, 1.);
+        let filtered = sum * Color{r: 0.5, g: 0.5, b: 0.5};
+        assert_approx_eq!(filtered.r, 2.);
+        assert_approx_eq!(filtered.max_channel(), 2.);
+        // Clamped only when quantised
+        assert_eq!(filtered.to_rgb8(), [255, 223, 128]);
+        assert_eq!(Color{r: -1., g: 0., b: 0.}.to_rgb8(), [0, 0, 0]);
+    }
+}
@@ -0,0 +1,38 @@
+use minifb::{Window, Key, WindowOptions};
+use crate::camera::Camera;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum UpdateStatus{
+    NotFinished,
+    Finished,
+    AboutToExit
+}
+
+// Interactive front end that shows the camera's buffer in a minifb window.
+// Rendering itself is done by the camera, so it works the same without the preview.
+pub struct Preview{
+    window: Window
+}
+
+impl Preview{
+    pub fn new(width: u32, height: u32) -> Preview
+    {
+        Preview{
+            window: Window::new("nrtrt", width as usize, height as usize, WindowOptions::default()).unwrap()
+        }
+    }
+
+    pub fn update(&mut self, camera: &Camera) -> UpdateStatus
+    {
+        self.window.update_with_buffer(&camera.to_u32_buffer()).unwrap();
+        match self.window.is_open() && !self.window.is_key_down(Key::Escape){
+            false => UpdateStatus::AboutToExit,
+            true => {
+                if camera.is_finished(){
+                    return UpdateStatus::Finished;
+                }
+                UpdateStatus::NotFinished
+            }
+        }
+    }
+}
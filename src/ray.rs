@@ -25,11 +25,20 @@ impl Ray
             direction: Vector::new()
         }
     }
+    // Start of a ray leaving the surface at the point: moved a bit along the normal (to the side the ray
+    // leaves to), so the ray doesn't hit the surface it starts on because of rounding errors of the point.
+    // The offset grows with the coordinates, because so do the rounding errors.
+    pub fn offset_origin(point: &Vector, normal: &Vector) -> Vector
+    {
+        let largest_coordinate = point.x.abs().max(point.y.abs()).max(point.z.abs());
+        *point + normal.normalized() * (1e-7 * (1. + largest_coordinate))
+    }
+
     //Calculate the closest distance of the point and the ray (it's 0 if ray comes trough the point)
     pub fn distance_to_point(&self, point: Vector) -> f64
     {
         let start_to_point = point - self.start_position;
-        return (self.direction * start_to_point).distance() / self.direction.distance();
+        (self.direction * start_to_point).distance() / self.direction.distance()
     }
 }
 
@@ -64,4 +73,16 @@ mod test
         let distance = ray.distance_to_point(point);
         assert_approx_eq!(distance, 5.047042);
     }
+
+    #[test]
+    fn offset_origin()
+    {
+        let normal = Vector{x: 0., y: 0., z: 2.};
+        let near = Ray::offset_origin(&Vector::new(), &normal);
+        assert!(near.z > 0. && near.z < 1e-6);
+        assert_eq!((near.x, near.y), (0., 0.));
+        // Points far from the origin are moved further
+        let far = Ray::offset_origin(&Vector{x: 0., y: 1000., z: 0.}, &normal);
+        assert!(far.z > 100. * near.z);
+    }
 }
\ No newline at end of file
@@ -14,7 +14,13 @@ pub struct RayNode{
     pub parent: NodeId,
     pub child: Vec<u32>,
     pub ray: Ray,
-    pub recursion_depth: u32
+    pub recursion_depth: u32,
+    // Part of the parent's light carried by this ray, used when the parent splits the light
+    // (i.e. into reflected and refracted part)
+    pub weight: f64,
+    // Whether the light that the ray hits counts, diffuse rays don't count it, because the lights
+    // are already sampled at the diffuse hit
+    pub counts_emission: bool
 }
 
 impl RayNode{
@@ -24,7 +30,9 @@ impl RayNode{
             parent,
             child: Vec::new(),
             ray: *ray,
-            recursion_depth
+            recursion_depth,
+            weight: 1.,
+            counts_emission: true
         }
     }
 
@@ -61,6 +69,10 @@ impl RayArena{
     }
 
     pub fn add_node(&mut self, parent: NodeId, ray: &Ray) -> NodeId{
+        self.add_weighted_node(parent, ray, 1.)
+    }
+
+    pub fn add_weighted_node(&mut self, parent: NodeId, ray: &Ray, weight: f64) -> NodeId{
         if let NodeId::Parent(_) = parent {
             if let Some(node) = self.get_node(parent) {
                 let recursion_depth = node.recursion_depth + 1;
@@ -68,17 +80,21 @@ impl RayArena{
                     return NodeId::Invalid;
                 }
                 let new_id = self.nodes.len() as u32;
-                self.nodes.insert(new_id, RayNode::new(new_id, parent, ray, recursion_depth));
+                let mut node = RayNode::new(new_id, parent, ray, recursion_depth);
+                node.weight = weight;
+                self.nodes.insert(new_id, node);
                 self.get_mut_node(parent).unwrap().add_child(new_id);
                 return NodeId::Parent(new_id);
             }
         }
         else if let NodeId::Root = parent{
             let new_id = self.nodes.len() as u32;
-            self.nodes.insert(new_id,RayNode::new(new_id, NodeId::Root, ray, 0));
+            let mut node = RayNode::new(new_id, NodeId::Root, ray, 0);
+            node.weight = weight;
+            self.nodes.insert(new_id, node);
             return NodeId::Parent(new_id);
         }
-        return NodeId::Invalid;
+        NodeId::Invalid
     }
 
     //Returns the ends (the nodes that have no childrens itself) of a given node
@@ -88,7 +104,7 @@ impl RayArena{
             if let Some(node) = self.get_node(id){
                 let mut result = Vec::<u32>::new();
                 //If it has no childrens
-                if node.child.len() == 0{
+                if node.child.is_empty(){
                     result.push(node.id);
                 }
                 else{
@@ -173,6 +189,19 @@ mod test
         assert_eq!(ray_arena.get_node(child_3).unwrap().child.len(), 0);
     }
 
+    #[test]
+    fn weighted_nodes(){
+        let mut ray_arena = RayArena::new(10);
+        let root_node = ray_arena.add_node(NodeId::Root, &Ray::new_empty());
+        let reflected = ray_arena.add_weighted_node(root_node, &Ray::new_empty(), 0.25);
+        let refracted = ray_arena.add_weighted_node(root_node, &Ray::new_empty(), 0.75);
+
+        assert_eq!(ray_arena.get_node(root_node).unwrap().weight, 1.);
+        assert_eq!(ray_arena.get_node(reflected).unwrap().weight, 0.25);
+        assert_eq!(ray_arena.get_node(refracted).unwrap().weight, 0.75);
+        assert_eq!(ray_arena.get_node(root_node).unwrap().child.len(), 2);
+    }
+
     #[test]
     fn check_max_recursion_depth(){
         let mut ray_arena = RayArena::new(2);
@@ -202,8 +231,8 @@ mod test
         let child_1 = ray_arena.add_node(root_node, &Ray::new_empty());
         let child_2 = ray_arena.add_node(root_node, &Ray::new_empty());
         let child_3 = ray_arena.add_node(child_1, &Ray::new_empty());
-        let child_4 = ray_arena.add_node(child_3, &Ray::new_empty());
-        let child_5 = ray_arena.add_node(child_1, &Ray::new_empty());
+        let _child_4 = ray_arena.add_node(child_3, &Ray::new_empty());
+        let _child_5 = ray_arena.add_node(child_1, &Ray::new_empty());
         let child_6 = ray_arena.add_node(child_2, &Ray::new_empty());
 
         /*
@@ -0,0 +1,165 @@
+// Random numbers of the render and the ways of turning them into points and directions.
+// Every sample draws its own numbers, so the noise is not correlated between the pixels
+// and goes away with more samples.
+use crate::vector::Vector;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::f64::consts;
+
+pub trait Sampler
+{
+    // Uniform number in [0, 1)
+    fn next_1d(&mut self) -> f64;
+
+    // Uniform point of the unit square
+    fn next_2d(&mut self) -> (f64, f64)
+    {
+        let x = self.next_1d();
+        (x, self.next_1d())
+    }
+}
+
+// Independent random numbers, the same seed always gives the same sequence
+pub struct RandomSampler
+{
+    rng: StdRng
+}
+
+impl RandomSampler
+{
+    pub fn new(seed: u64) -> RandomSampler
+    {
+        RandomSampler{
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+
+    // Numbers of a single pixel, they depend only on the seed and the pixel, not on the chunk or
+    // the thread that renders it. The index is mixed (SplitMix64), so the neighbouring pixels get
+    // unrelated streams.
+    pub fn for_pixel(seed: u64, pixel_index: u64) -> RandomSampler
+    {
+        let mut mixed = pixel_index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        RandomSampler::new(seed ^ mixed ^ (mixed >> 31))
+    }
+}
+
+impl Sampler for RandomSampler
+{
+    fn next_1d(&mut self) -> f64
+    {
+        self.rng.gen()
+    }
+}
+
+// Maps the point of the unit square uniformly to the unit disk, keeping the neighbouring points
+// together (Shirley's concentric mapping)
+pub fn concentric_disk(sample: (f64, f64)) -> (f64, f64)
+{
+    let (a, b) = (2. * sample.0 - 1., 2. * sample.1 - 1.);
+    if a == 0. && b == 0.
+    {
+        return (0., 0.);
+    }
+    let (radius, angle) = if a.abs() > b.abs()
+    {
+        (a, consts::FRAC_PI_4 * (b / a))
+    }
+    else
+    {
+        (b, consts::FRAC_PI_2 - consts::FRAC_PI_4 * (a / b))
+    };
+    (radius * angle.cos(), radius * angle.sin())
+}
+
+// Unit direction on the side of the normal, with the probability proportional to the cosine
+// of the angle to the normal (cos / pi), which is how a diffuse surface scatters the light.
+// The normal has to be normalized.
+pub fn cosine_hemisphere(normal: &Vector, sample: (f64, f64)) -> Vector
+{
+    let (x, y) = concentric_disk(sample);
+    let z = (1. - x * x - y * y).max(0.).sqrt();
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    tangent * x + bitangent * y + *normal * z
+}
+
+// Two unit vectors perpendicular to the normal and to each other (Duff et al.)
+pub fn orthonormal_basis(normal: &Vector) -> (Vector, Vector)
+{
+    let sign = 1f64.copysign(normal.z);
+    let a = -1. / (sign + normal.z);
+    let b = normal.x * normal.y * a;
+    (Vector{x: 1. + sign * normal.x * normal.x * a, y: sign * b, z: -sign * normal.x},
+     Vector{x: b, y: sign + normal.y * normal.y * a, z: -normal.y})
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::sampler::{Sampler, RandomSampler, concentric_disk, cosine_hemisphere};
+    use crate::vector::Vector;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn same_seed_same_numbers()
+    {
+        let mut first = RandomSampler::new(42);
+        let mut second = RandomSampler::new(42);
+        let mut other = RandomSampler::new(43);
+        let numbers: Vec<f64> = (0..10).map(|_| first.next_1d()).collect();
+        assert_eq!(numbers, (0..10).map(|_| second.next_1d()).collect::<Vec<f64>>());
+        assert_ne!(numbers, (0..10).map(|_| other.next_1d()).collect::<Vec<f64>>());
+        assert!(numbers.iter().all(|number| (0. ..1.).contains(number)));
+
+        // Every pixel gets its own stream
+        let pixel_numbers = |seed, index| {
+            let mut sampler = RandomSampler::for_pixel(seed, index);
+            (0..10).map(|_| sampler.next_1d()).collect::<Vec<f64>>()
+        };
+        assert_eq!(pixel_numbers(1, 5), pixel_numbers(1, 5));
+        assert_ne!(pixel_numbers(1, 5), pixel_numbers(1, 6));
+        assert_ne!(pixel_numbers(1, 5), pixel_numbers(2, 5));
+    }
+
+    #[test]
+    fn disk_points()
+    {
+        assert_eq!(concentric_disk((0.5, 0.5)), (0., 0.));
+        let (x, y) = concentric_disk((1., 0.5));
+        assert_approx_eq!(x, 1.);
+        assert_approx_eq!(y, 0.);
+        for sample in [(0., 0.), (1., 1.), (0.3, 0.95), (0.01, 0.6)].iter()
+        {
+            let (x, y) = concentric_disk(*sample);
+            assert!(x * x + y * y <= 1. + 1e-9);
+        }
+    }
+
+    #[test]
+    fn cosine_weighted_directions()
+    {
+        let mut sampler = RandomSampler::new(7);
+        let normals = [Vector{x: 0., y: 0., z: 1.}, Vector{x: 0., y: 0., z: -1.},
+                       Vector{x: 1., y: -2., z: 0.5}.normalized()];
+        for normal in normals.iter()
+        {
+            let num_of_samples = 20000;
+            let mut cosine_sum = 0.;
+            let mut sum = Vector::new();
+            for _ in 0..num_of_samples
+            {
+                let direction = cosine_hemisphere(normal, sampler.next_2d());
+                assert_approx_eq!(direction.distance(), 1.);
+                let cosine = direction.dot(*normal);
+                assert!(cosine >= 0.);
+                cosine_sum += cosine;
+                sum = sum + direction;
+            }
+            // Mean cosine of the distribution is 2/3 and it is symmetric around the normal
+            assert_approx_eq!(cosine_sum / num_of_samples as f64, 2. / 3., 0.01);
+            let mean = sum * (1. / num_of_samples as f64);
+            assert!((mean - *normal * mean.dot(*normal)).distance() < 0.02);
+        }
+    }
+}
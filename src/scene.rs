@@ -0,0 +1,1081 @@
+// Loader of the text scene description.
+//
+// Every non empty line describes one item: a directive followed by properties, each property is
+// a name followed by a fixed number of values. Everything after '#' is a comment. For example:
+//
+//     render width 400 height 300 fov 70 spp 16 filter mitchell exposure 0.5 tonemap aces
+//     camera position 0 0 5 target 0 0 -5 up 0 -1 0 aperture 0.1
+//     material gold color 0.86 0.86 0.08 reflectivity 0.9 specular true
+//     sphere position 3.2 2 -9 radius 2.5 material gold
+//     sphere position 0 0 -5 radius 1 color 1 0 0 reflectivity 0.5 specular false
+//     rectangle corner -10 3.8 -20 edge1 20 0 0 edge2 0 0 20 material gold
+//     triangle a -1 0 -4 b 1 0 -4 c 0 -1 -4 color 0 0 1
+//     mesh file models/teapot.obj position 0 3.8 -6 scale 0.5
+//     light position 10000 -10000 10000 intensity 4.74e9 color 1 0.95 0.9
+//     light position 0 -4 -6 intensity 300 shape disk normal 0 1 0 radius 1
+//     sun direction -1 1 -1 intensity 1.26 diameter 0.53
+//     spot position 0 -5 -3 target 0 3.8 -6 intensity 2000 angle 25 falloff 5
+//
+// The camera looks either in a direction or at a target, the y axis grows downwards so by default up is 0 -1 0.
+// The field of view is horizontal (fov) or vertical (vfov), the other one follows from the size of the image.
+// Camera with an aperture (radius of the lens) blurs everything that is not at the focus distance (by default
+// the distance to the target).
+// Rectangles are parallelograms spanned by two edges starting at the corner, their normal is edge1 x edge2.
+// Triangles are given by their vertices, the normal is (b - a) x (c - a).
+// Meshes are loaded from OBJ files (relative to the scene file), scaled and then moved by the position.
+// They use the materials from the MTL files, unless the mesh line gives its own material.
+// Materials with the index of refraction (ior) are transparent, i.e. "ior 1.5" makes glass tinted by its color.
+// Projection is one of perspective, orthographic (showing "extent" world units along the fov axis), fisheye
+// (fov up to 360 degrees) or equirectangular (whole sphere around the camera).
+// Every pixel gets "spp" samples combined with the filter: box, tent, gaussian or mitchell.
+// Tone mapping is one of clamp, reinhard, extended-reinhard (with "white" luminance) or aces.
+// Lights are points with the intensity as their radiant power (in watts), which falls off with the square of
+// the distance, optionally tinted by a color. Area lights give soft shadows and can be seen by the camera:
+// "shape sphere radius R", "shape rectangle edge1 .. edge2 .." (centered at the position, shining to the side
+// of edge1 x edge2) or "shape disk normal .. radius R" (shining to the side of the normal).
+// Sun is a light from far away, its intensity is the irradiance of a surface facing it and its "diameter" (in degrees,
+// 0.53 for the real sun) softens the shadows. Spot shines from the position into a cone around the direction (or
+// towards the target), the "angle" (in degrees) is from the axis to the edge of the cone, the last "falloff"
+// degrees of which fade out smoothly.
+// Colors are given as floats in the 0..1 range. Shapes either use a material declared earlier
+// (by name) or describe it inline. Materials with "emission" (radiance, can be above 1) glow by themselves,
+// i.e. "rectangle ... color 1 1 1 emission 8 8 8" is a light panel.
+use crate::camera::Camera;
+use crate::lightsource::{Lightsource, LightShape, DirectionalLight, SpotLight};
+use crate::material::Material;
+use crate::obj::ObjModel;
+use crate::pixel::Color;
+use crate::settings::{RenderSettings, SettingsError, FovAxis};
+use crate::tonemap::ToneMapping;
+use crate::shapes;
+use crate::vector::Vector;
+use crate::view::{View, Lens, Projection};
+use crate::filter::PixelFilter;
+use crate::world::World;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::{error, fmt, fs, io};
+
+#[derive(Debug)]
+pub enum SceneError
+{
+    Io(io::Error),
+    // Malformed input, line numbers start from 1
+    Parse{line: usize, message: String}
+}
+
+impl fmt::Display for SceneError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            SceneError::Io(error) => write!(f, "cannot read the scene: {}", error),
+            SceneError::Parse{line, message} => write!(f, "line {}: {}", line, message)
+        }
+    }
+}
+
+impl error::Error for SceneError {}
+
+impl From<io::Error> for SceneError
+{
+    fn from(error: io::Error) -> SceneError
+    {
+        SceneError::Io(error)
+    }
+}
+
+pub struct Scene
+{
+    pub world: World,
+    pub settings: RenderSettings,
+    pub view: View,
+    pub lens: Lens,
+    // Problems that don't stop the scene from rendering, i.e. unsupported parts of the meshes
+    pub warnings: Vec<String>,
+}
+
+// Names and number of values of the properties that every directive accepts
+const RENDER_PROPERTIES: &[(&str, usize)] = &[("width", 1), ("height", 1), ("fov", 1), ("vfov", 1), ("depth", 1),
+                                              ("rays", 1), ("falloff", 1), ("chunk", 2), ("seed", 1),
+                                              ("exposure", 1), ("tonemap", 1), ("white", 1), ("projection", 1),
+                                              ("extent", 1), ("spp", 1), ("filter", 1)];
+const CAMERA_PROPERTIES: &[(&str, usize)] = &[("position", 3), ("direction", 3), ("target", 3), ("up", 3),
+                                              ("aperture", 1), ("focus", 1)];
+const MATERIAL_PROPERTIES: &[(&str, usize)] = &[("color", 3), ("reflectivity", 1), ("specular", 1), ("ior", 1),
+                                                ("emission", 3)];
+// Shapes also accept every material property, see Properties::parse_shape
+const SPHERE_PROPERTIES: &[(&str, usize)] = &[("position", 3), ("radius", 1), ("material", 1)];
+const RECTANGLE_PROPERTIES: &[(&str, usize)] = &[("corner", 3), ("edge1", 3), ("edge2", 3), ("material", 1)];
+const TRIANGLE_PROPERTIES: &[(&str, usize)] = &[("a", 3), ("b", 3), ("c", 3), ("material", 1)];
+const MESH_PROPERTIES: &[(&str, usize)] = &[("file", 1), ("position", 3), ("scale", 1), ("material", 1)];
+const LIGHT_PROPERTIES: &[(&str, usize)] = &[("position", 3), ("intensity", 1), ("color", 3), ("shape", 1),
+                                             ("radius", 1), ("edge1", 3), ("edge2", 3), ("normal", 3)];
+const SUN_PROPERTIES: &[(&str, usize)] = &[("direction", 3), ("intensity", 1), ("color", 3), ("diameter", 1)];
+const SPOT_PROPERTIES: &[(&str, usize)] = &[("position", 3), ("direction", 3), ("target", 3), ("intensity", 1),
+                                            ("color", 3), ("angle", 1), ("falloff", 1)];
+
+// Properties of a single line
+struct Properties<'a>
+{
+    line: usize,
+    directive: &'a str,
+    values: HashMap<&'a str, Vec<&'a str>>
+}
+
+impl<'a> Properties<'a>
+{
+    fn parse(line: usize, directive: &'a str, tokens: &[&'a str], allowed: &[(&str, usize)]) -> Result<Properties<'a>, SceneError>
+    {
+        let mut properties = Properties{
+            line,
+            directive,
+            values: HashMap::new()
+        };
+        let mut idx = 0;
+        while idx < tokens.len()
+        {
+            let name = tokens[idx];
+            let num_of_values = match allowed.iter().find(|(allowed_name, _)| *allowed_name == name)
+            {
+                Some((_, num_of_values)) => *num_of_values,
+                None => return Err(properties.error(format!("unknown property '{}' of {}", name, directive)))
+            };
+            // Values end early when a name of another property shows up, i.e. "position 0 0 radius 1"
+            let values = &tokens[idx + 1..];
+            let values = match values.iter().position(|value| allowed.iter().any(|(allowed_name, _)| allowed_name == value))
+            {
+                Some(next_property) => &values[..next_property],
+                None => values
+            };
+            if values.len() < num_of_values
+            {
+                return Err(properties.error(format!("property '{}' of {} needs {} value(s), found {}",
+                                                    name, directive, num_of_values, values.len())));
+            }
+            if properties.values.insert(name, values[..num_of_values].to_vec()).is_some()
+            {
+                return Err(properties.error(format!("property '{}' of {} is given more than once", name, directive)));
+            }
+            idx += 1 + num_of_values;
+        }
+        Ok(properties)
+    }
+
+    // Parses a shape directive, which takes the inline material properties next to its own
+    fn parse_shape(line: usize, directive: &'a str, tokens: &[&'a str], allowed: &[(&str, usize)]) -> Result<Properties<'a>, SceneError>
+    {
+        Properties::parse(line, directive, tokens, &[allowed, MATERIAL_PROPERTIES].concat())
+    }
+
+    fn error(&self, message: String) -> SceneError
+    {
+        SceneError::Parse{line: self.line, message}
+    }
+
+    fn has(&self, name: &str) -> bool
+    {
+        self.values.contains_key(name)
+    }
+
+    fn required(&self, name: &str) -> Result<&Vec<&'a str>, SceneError>
+    {
+        match self.values.get(name)
+        {
+            Some(values) => Ok(values),
+            None => Err(self.error(format!("{} is missing property '{}'", self.directive, name)))
+        }
+    }
+
+    fn parse_value<T: std::str::FromStr>(&self, name: &str, value: &str, expected: &str) -> Result<T, SceneError>
+    {
+        value.parse::<T>().map_err(|_| self.error(format!("value '{}' of property '{}' is not {}", value, name, expected)))
+    }
+
+    // Finite number, 'nan' and 'inf' parse as floats but make no sense in the scene
+    fn parse_number(&self, name: &str, value: &str) -> Result<f64, SceneError>
+    {
+        match self.parse_value::<f64>(name, value, "a number")?
+        {
+            number if number.is_finite() => Ok(number),
+            _ => Err(self.error(format!("value '{}' of property '{}' is not a finite number", value, name)))
+        }
+    }
+
+    fn number(&self, name: &str) -> Result<f64, SceneError>
+    {
+        let values = self.required(name)?;
+        self.parse_number(name, values[0])
+    }
+
+    fn integer(&self, name: &str) -> Result<u32, SceneError>
+    {
+        let values = self.required(name)?;
+        self.parse_value(name, values[0], "a non negative integer")
+    }
+
+    fn boolean(&self, name: &str) -> Result<bool, SceneError>
+    {
+        let values = self.required(name)?;
+        self.parse_value(name, values[0], "'true' or 'false'")
+    }
+
+    fn word(&self, name: &str) -> Result<&'a str, SceneError>
+    {
+        Ok(self.required(name)?[0])
+    }
+
+    fn vector(&self, name: &str) -> Result<Vector, SceneError>
+    {
+        let values = self.required(name)?;
+        Ok(Vector{
+            x: self.parse_number(name, values[0])?,
+            y: self.parse_number(name, values[1])?,
+            z: self.parse_number(name, values[2])?
+        })
+    }
+
+    fn color(&self, name: &str) -> Result<Color, SceneError>
+    {
+        let channels = self.vector(name)?;
+        for channel in [channels.x, channels.y, channels.z].iter()
+        {
+            if *channel < 0. || *channel > 1.
+            {
+                return Err(self.error(format!("channels of '{}' have to be in range 0..1", name)));
+            }
+        }
+        Ok(Color{
+            r: channels.x,
+            g: channels.y,
+            b: channels.z
+        })
+    }
+
+    fn fraction(&self, name: &str) -> Result<f64, SceneError>
+    {
+        let value = self.number(name)?;
+        if !(0. ..=1.).contains(&value)
+        {
+            return Err(self.error(format!("property '{}' of {} has to be in range 0..1", name, self.directive)));
+        }
+        Ok(value)
+    }
+
+    fn non_negative(&self, name: &str) -> Result<f64, SceneError>
+    {
+        let value = self.number(name)?;
+        if value < 0.
+        {
+            return Err(self.error(format!("property '{}' of {} cannot be negative", name, self.directive)));
+        }
+        Ok(value)
+    }
+
+    fn positive(&self, name: &str) -> Result<f64, SceneError>
+    {
+        let value = self.number(name)?;
+        if value <= 0.
+        {
+            return Err(self.error(format!("property '{}' of {} has to be positive", name, self.directive)));
+        }
+        Ok(value)
+    }
+}
+
+impl Scene
+{
+    pub fn load(path: &str) -> Result<Scene, SceneError>
+    {
+        let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        Scene::parse_in(&fs::read_to_string(path)?, directory)
+    }
+
+    // Parses the scene, paths of the meshes are relative to the working directory
+    pub fn parse(text: &str) -> Result<Scene, SceneError>
+    {
+        Scene::parse_in(text, Path::new(""))
+    }
+
+    // Parses the scene, paths of the meshes are relative to the directory
+    pub fn parse_in(text: &str, directory: &Path) -> Result<Scene, SceneError>
+    {
+        let mut scene = Scene{
+            world: World::new(),
+            settings: RenderSettings::new(),
+            view: View::look_in(&Vector{x: 0., y: 0., z: 5.}, &Vector{x: 0., y: 0., z: -1.}, &View::default_up()).unwrap(),
+            lens: Lens::pinhole(),
+            warnings: Vec::new(),
+        };
+        let mut materials: HashMap<&str, (Material, u32)> = HashMap::new();
+        // Materials get ids in the order of declaration, named ones and inline ones alike
+        let mut num_of_materials = 0;
+
+        for (idx, line) in text.lines().enumerate()
+        {
+            let line_num = idx + 1;
+            let content = match line.find('#')
+            {
+                Some(comment_start) => &line[..comment_start],
+                None => line
+            };
+            let tokens: Vec<&str> = content.split_whitespace().collect();
+            let (directive, tokens) = match tokens.split_first()
+            {
+                Some((directive, tokens)) => (*directive, tokens),
+                None => continue
+            };
+
+            match directive
+            {
+                "render" => {
+                    let properties = Properties::parse(line_num, directive, tokens, RENDER_PROPERTIES)?;
+                    scene.parse_render(&properties)?;
+                },
+                "camera" => {
+                    let properties = Properties::parse(line_num, directive, tokens, CAMERA_PROPERTIES)?;
+                    let (view, lens) = Scene::parse_camera(&properties)?;
+                    scene.view = view;
+                    scene.lens = lens;
+                },
+                "material" => {
+                    let (name, tokens) = match tokens.split_first()
+                    {
+                        Some((name, tokens)) => (*name, tokens),
+                        None => return Err(SceneError::Parse{line: line_num, message: "material needs a name".to_string()})
+                    };
+                    let properties = Properties::parse(line_num, directive, tokens, MATERIAL_PROPERTIES)?;
+                    num_of_materials += 1;
+                    materials.insert(name, (Scene::parse_material(&properties)?, num_of_materials));
+                },
+                "sphere" => {
+                    let properties = Properties::parse_shape(line_num, directive, tokens, SPHERE_PROPERTIES)?;
+                    let (material, material_id) = Scene::shape_material(&properties, &materials, &mut num_of_materials)?;
+                    scene.world.add_shape_with_material_id(Arc::new(shapes::Sphere{
+                        radius: properties.positive("radius")?,
+                        position: properties.vector("position")?,
+                        material
+                    }), material_id);
+                },
+                "rectangle" => {
+                    let properties = Properties::parse_shape(line_num, directive, tokens, RECTANGLE_PROPERTIES)?;
+                    let first_edge = properties.vector("edge1")?;
+                    let second_edge = properties.vector("edge2")?;
+                    if (first_edge * second_edge).distance() == 0.
+                    {
+                        return Err(properties.error("edges of the rectangle cannot be parallel or empty".to_string()));
+                    }
+                    let (material, material_id) = Scene::shape_material(&properties, &materials, &mut num_of_materials)?;
+                    scene.world.add_shape_with_material_id(Arc::new(shapes::Rectangle::new(&properties.vector("corner")?,
+                                                                                           &first_edge,
+                                                                                           &second_edge,
+                                                                                           &material)),
+                                                           material_id);
+                },
+                "triangle" => {
+                    let properties = Properties::parse_shape(line_num, directive, tokens, TRIANGLE_PROPERTIES)?;
+                    let a = properties.vector("a")?;
+                    let b = properties.vector("b")?;
+                    let c = properties.vector("c")?;
+                    if ((b - a) * (c - a)).distance() == 0.
+                    {
+                        return Err(properties.error("vertices of the triangle cannot lie on one line".to_string()));
+                    }
+                    let (material, material_id) = Scene::shape_material(&properties, &materials, &mut num_of_materials)?;
+                    scene.world.add_shape_with_material_id(Arc::new(shapes::Triangle::new(&a, &b, &c, &material)), material_id);
+                },
+                "mesh" => {
+                    let properties = Properties::parse_shape(line_num, directive, tokens, MESH_PROPERTIES)?;
+                    let mut model = ObjModel::load(&directory.join(properties.word("file")?))
+                        .map_err(|error| properties.error(error.to_string()))?;
+                    let scale = if properties.has("scale") {properties.positive("scale")?} else {1.};
+                    let position = if properties.has("position") {properties.vector("position")?} else {Vector::new()};
+                    model.transform(scale, &position);
+                    let has_material = properties.has("material") || Scene::has_inline_material(&properties);
+                    let material = if has_material {Some(Scene::shape_material(&properties, &materials, &mut num_of_materials)?)} else {None};
+                    let meshes = model.meshes(material.as_ref().map(|(material, _)| material));
+                    if meshes.is_empty()
+                    {
+                        scene.warnings.push(format!("line {}: mesh has no faces", line_num));
+                    }
+                    // Meshes are made in the order of the groups, groups with the same MTL material share the id
+                    let mut group_material_ids: HashMap<Option<&String>, u32> = HashMap::new();
+                    for (mesh, group) in meshes.into_iter().zip(model.groups.iter())
+                    {
+                        let material_id = match material
+                        {
+                            Some((_, material_id)) => material_id,
+                            None => *group_material_ids.entry(group.material.as_ref()).or_insert_with(|| {
+                                num_of_materials += 1;
+                                num_of_materials
+                            })
+                        };
+                        scene.world.add_shape_with_material_id(Arc::new(mesh), material_id);
+                    }
+                    scene.warnings.extend(model.warnings);
+                },
+                "light" => {
+                    let properties = Properties::parse(line_num, directive, tokens, LIGHT_PROPERTIES)?;
+                    let color = if properties.has("color") {properties.color("color")?} else {Color::white()};
+                    scene.world.add_light(Arc::new(Lightsource::new_area(&properties.vector("position")?,
+                                                                         properties.non_negative("intensity")?, color,
+                                                                         Scene::parse_light_shape(&properties)?)));
+                },
+                "sun" => {
+                    let properties = Properties::parse(line_num, directive, tokens, SUN_PROPERTIES)?;
+                    scene.world.add_light(Arc::new(Scene::parse_sun(&properties)?));
+                },
+                "spot" => {
+                    let properties = Properties::parse(line_num, directive, tokens, SPOT_PROPERTIES)?;
+                    scene.world.add_light(Arc::new(Scene::parse_spot(&properties)?));
+                },
+                _ => return Err(SceneError::Parse{line: line_num, message: format!("unknown directive '{}'", directive)})
+            }
+        }
+        Ok(scene)
+    }
+
+    fn parse_render(&mut self, properties: &Properties) -> Result<(), SceneError>
+    {
+        let settings = &mut self.settings;
+        if properties.has("width")
+        {
+            settings.width = properties.integer("width")?;
+        }
+        if properties.has("height")
+        {
+            settings.height = properties.integer("height")?;
+        }
+        if properties.has("fov") && properties.has("vfov")
+        {
+            return Err(properties.error("only one of fov and vfov can be given".to_string()));
+        }
+        if properties.has("fov")
+        {
+            settings.fov = properties.positive("fov")?;
+            settings.fov_axis = FovAxis::Horizontal;
+        }
+        if properties.has("vfov")
+        {
+            settings.fov = properties.positive("vfov")?;
+            settings.fov_axis = FovAxis::Vertical;
+        }
+        if properties.has("depth")
+        {
+            settings.max_ray_depth = properties.integer("depth")?;
+        }
+        if properties.has("rays")
+        {
+            settings.num_of_reflected_rays = properties.integer("rays")? as usize;
+        }
+        if properties.has("falloff")
+        {
+            settings.scattered_rays_falloff = properties.positive("falloff")?;
+        }
+        if properties.has("chunk")
+        {
+            let chunk = properties.required("chunk")?;
+            settings.width_chunk = properties.parse_value("chunk", chunk[0], "a non negative integer")?;
+            settings.height_chunk = properties.parse_value("chunk", chunk[1], "a non negative integer")?;
+        }
+        if properties.has("seed")
+        {
+            let seed = properties.required("seed")?;
+            settings.seed = properties.parse_value("seed", seed[0], "a non negative integer")?;
+        }
+        if properties.has("exposure")
+        {
+            settings.exposure = properties.number("exposure")?;
+        }
+        if properties.has("tonemap")
+        {
+            let name = properties.word("tonemap")?;
+            settings.tone_mapping = match ToneMapping::from_name(name)
+            {
+                Some(tone_mapping) => tone_mapping,
+                None => return Err(properties.error(format!("unknown tone mapping '{}'", name)))
+            };
+        }
+        if properties.has("white")
+        {
+            match settings.tone_mapping
+            {
+                ToneMapping::ExtendedReinhard{..} => settings.tone_mapping = ToneMapping::ExtendedReinhard{white_point: properties.positive("white")?},
+                _ => return Err(properties.error("white point can be used only with extended-reinhard tone mapping".to_string()))
+            }
+        }
+        if properties.has("spp")
+        {
+            settings.samples_per_pixel = properties.integer("spp")?;
+        }
+        if properties.has("filter")
+        {
+            let name = properties.word("filter")?;
+            settings.pixel_filter = match PixelFilter::from_name(name)
+            {
+                Some(filter) => filter,
+                None => return Err(properties.error(format!("unknown pixel filter '{}'", name)))
+            };
+        }
+        if properties.has("projection")
+        {
+            let name = properties.word("projection")?;
+            settings.projection = match Projection::from_name(name)
+            {
+                Some(projection) => projection,
+                None => return Err(properties.error(format!("unknown projection '{}'", name)))
+            };
+        }
+        if properties.has("extent")
+        {
+            match settings.projection
+            {
+                Projection::Orthographic{..} => settings.projection = Projection::Orthographic{extent: properties.positive("extent")?},
+                _ => return Err(properties.error("extent can be used only with orthographic projection".to_string()))
+            }
+        }
+        Ok(())
+    }
+
+    // Camera looks either in the direction or at the target, by default from (0, 0, 5) along -Z.
+    // With the aperture the lens focuses at the focus distance, or at the target when there is none.
+    fn parse_camera(properties: &Properties) -> Result<(View, Lens), SceneError>
+    {
+        let position = if properties.has("position") {properties.vector("position")?} else {Vector{x: 0., y: 0., z: 5.}};
+        let up = if properties.has("up") {properties.vector("up")?} else {View::default_up()};
+        let direction = match (properties.has("direction"), properties.has("target"))
+        {
+            (true, true) => return Err(properties.error("camera cannot have both a direction and a target".to_string())),
+            (true, false) => properties.vector("direction")?,
+            (false, true) => properties.vector("target")? - position,
+            (false, false) => Vector{x: 0., y: 0., z: -1.}
+        };
+        if direction.distance() == 0.
+        {
+            return Err(properties.error("camera has to look somewhere, the direction is empty or the target is at its position".to_string()));
+        }
+        let view = View::look_in(&position, &direction, &up)
+            .ok_or_else(|| properties.error("up vector of the camera cannot be parallel to its direction".to_string()))?;
+
+        let mut lens = Lens::pinhole();
+        if properties.has("aperture")
+        {
+            lens.aperture_radius = properties.number("aperture")?;
+            if lens.aperture_radius < 0.
+            {
+                return Err(properties.error("aperture of the camera cannot be negative".to_string()));
+            }
+        }
+        if properties.has("focus")
+        {
+            lens.focus_distance = properties.positive("focus")?;
+        }
+        else if properties.has("target")
+        {
+            // Distance along the view direction, which is the distance to the target
+            lens.focus_distance = direction.distance();
+        }
+        else if lens.aperture_radius > 0.
+        {
+            return Err(properties.error("camera with an aperture needs a focus distance or a target".to_string()));
+        }
+        Ok((view, lens))
+    }
+
+    fn parse_light_shape(properties: &Properties) -> Result<LightShape, SceneError>
+    {
+        let name = if properties.has("shape") {properties.word("shape")?} else {"point"};
+        let (shape, used): (LightShape, &[&str]) = match name
+        {
+            "point" => (LightShape::Point, &[]),
+            "sphere" => (LightShape::Sphere{radius: properties.positive("radius")?}, &["radius"]),
+            "rectangle" => {
+                let (edge1, edge2) = (properties.vector("edge1")?, properties.vector("edge2")?);
+                if (edge1 * edge2).distance() == 0.
+                {
+                    return Err(properties.error("edges of the rectangle light cannot be parallel or empty".to_string()));
+                }
+                (LightShape::Rectangle{edge1, edge2}, &["edge1", "edge2"])
+            },
+            "disk" => {
+                let normal = properties.vector("normal")?;
+                if normal.distance() == 0.
+                {
+                    return Err(properties.error("normal of the disk light cannot be empty".to_string()));
+                }
+                (LightShape::Disk{normal: normal.normalized(), radius: properties.positive("radius")?}, &["normal", "radius"])
+            },
+            _ => return Err(properties.error(format!("unknown light shape '{}'", name)))
+        };
+        for property in ["radius", "edge1", "edge2", "normal"].iter()
+        {
+            if properties.has(property) && !used.contains(property)
+            {
+                return Err(properties.error(format!("property '{}' doesn't belong to the {} light", property, name)));
+            }
+        }
+        Ok(shape)
+    }
+
+    fn parse_sun(properties: &Properties) -> Result<DirectionalLight, SceneError>
+    {
+        let direction = properties.vector("direction")?;
+        if direction.distance() == 0.
+        {
+            return Err(properties.error("direction of the sun cannot be empty".to_string()));
+        }
+        let color = if properties.has("color") {properties.color("color")?} else {Color::white()};
+        let diameter = if properties.has("diameter") {properties.number("diameter")?} else {0.};
+        if !(0. ..180.).contains(&diameter)
+        {
+            return Err(properties.error("diameter of the sun has to be at least 0 and less than 180 degrees".to_string()));
+        }
+        Ok(DirectionalLight::new(&direction, properties.non_negative("intensity")?, color, diameter))
+    }
+
+    fn parse_spot(properties: &Properties) -> Result<SpotLight, SceneError>
+    {
+        let position = properties.vector("position")?;
+        let direction = match (properties.has("direction"), properties.has("target"))
+        {
+            (true, true) => return Err(properties.error("spot cannot have both a direction and a target".to_string())),
+            (true, false) => properties.vector("direction")?,
+            (false, true) => properties.vector("target")? - position,
+            (false, false) => return Err(properties.error("spot needs a direction or a target".to_string()))
+        };
+        if direction.distance() == 0.
+        {
+            return Err(properties.error("spot has to shine somewhere, the direction is empty or the target is at its position".to_string()));
+        }
+        let color = if properties.has("color") {properties.color("color")?} else {Color::white()};
+        let angle = properties.positive("angle")?;
+        let falloff = if properties.has("falloff") {properties.number("falloff")?} else {0.};
+        if angle > 180.
+        {
+            return Err(properties.error("angle of the spot cannot be more than 180 degrees".to_string()));
+        }
+        if falloff < 0. || falloff > angle
+        {
+            return Err(properties.error("falloff of the spot has to be between 0 and its angle".to_string()));
+        }
+        Ok(SpotLight::new(&position, &direction, properties.non_negative("intensity")?, color, angle, falloff))
+    }
+
+    fn parse_material(properties: &Properties) -> Result<Material, SceneError>
+    {
+        let mut material = Material::default();
+        if properties.has("color")
+        {
+            material.color = properties.color("color")?;
+        }
+        if properties.has("reflectivity")
+        {
+            // More would send out more light than the surface gets
+            material.reflectivity = properties.fraction("reflectivity")?;
+        }
+        if properties.has("specular")
+        {
+            material.is_specular = properties.boolean("specular")?;
+        }
+        if properties.has("ior")
+        {
+            material.refractive_index = Some(properties.positive("ior")?);
+        }
+        if properties.has("emission")
+        {
+            // Radiance, so unlike the colors it can be above 1
+            let emission = properties.vector("emission")?;
+            if emission.x < 0. || emission.y < 0. || emission.z < 0.
+            {
+                return Err(properties.error("emission cannot be negative".to_string()));
+            }
+            material.emission = Color{r: emission.x, g: emission.y, b: emission.z};
+        }
+        Ok(material)
+    }
+
+    // Whether the line describes its own material, the shapes accept all the properties of the materials
+    fn has_inline_material(properties: &Properties) -> bool
+    {
+        MATERIAL_PROPERTIES.iter().any(|(name, _)| properties.has(name))
+    }
+
+    // Material of a shape and its id, either the named one or the inline description (which gets a new id)
+    fn shape_material(properties: &Properties, materials: &HashMap<&str, (Material, u32)>, num_of_materials: &mut u32)
+        -> Result<(Material, u32), SceneError>
+    {
+        if !properties.has("material")
+        {
+            *num_of_materials += 1;
+            return Ok((Scene::parse_material(properties)?, *num_of_materials));
+        }
+        if Scene::has_inline_material(properties)
+        {
+            return Err(properties.error(format!("{} cannot use both a named material and inline material properties", properties.directive)));
+        }
+        let name = properties.word("material")?;
+        match materials.get(name)
+        {
+            Some(material) => Ok(*material),
+            None => Err(properties.error(format!("unknown material '{}'", name)))
+        }
+    }
+
+    // Creates the camera that renders the scene with its settings, fails if the settings are invalid
+    pub fn camera(&self) -> Result<Camera, SettingsError>
+    {
+        let mut camera = Camera::new(&self.settings)?;
+        camera.view = self.view;
+        camera.lens = self.lens;
+        Ok(camera)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::scene::{Scene, SceneError, Properties, MATERIAL_PROPERTIES, SPHERE_PROPERTIES, RECTANGLE_PROPERTIES,
+                       TRIANGLE_PROPERTIES, MESH_PROPERTIES};
+    use crate::tonemap::ToneMapping;
+    use crate::settings::FovAxis;
+    use crate::view::Projection;
+    use crate::filter::PixelFilter;
+    use crate::ray::Ray;
+    use crate::vector::Vector;
+    use assert_approx_eq::assert_approx_eq;
+    use std::f64::consts;
+
+    fn get_error_line(text: &str) -> (usize, String)
+    {
+        match Scene::parse(text)
+        {
+            Err(SceneError::Parse{line, message}) => (line, message),
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("scene should not be parsed")
+        }
+    }
+
+    #[test]
+    fn parse_scene()
+    {
+        let scene = Scene::parse("
+            # Comment
+            render width 200 height 100 fov 60 depth 2 rays 10 chunk 10 20 seed 3 exposure -1 tonemap extended-reinhard white 2
+            render spp 9 filter gaussian
+            camera position 1 2 3 direction 0 0 -1  # Comment after the camera
+
+            material red color 1 0 0 reflectivity 0.5 specular false
+            sphere position 0 0 -5 radius 1 material red
+            sphere position 0 0 5 radius 2 color 0 1 0
+            material glass color 1 1 1 ior 1.5
+            sphere position 0 -10 -5 radius 1 material glass
+            rectangle corner -5 3 -5 edge1 10 0 0 edge2 0 0 10 material red
+            triangle a 3 -1 -8 b 5 -1 -8 c 4 1 -8 color 0 0 1
+            light position 10 10 10 intensity 0.4
+            light position 0 -10 0 intensity 1000 color 1 0.5 0.25
+        ").unwrap();
+
+        assert_eq!(scene.settings.width, 200);
+        assert_eq!(scene.settings.height, 100);
+        assert_approx_eq!(scene.settings.fov, 60.);
+        assert_eq!(scene.settings.max_ray_depth, 2);
+        assert_eq!(scene.settings.num_of_reflected_rays, 10);
+        assert_eq!(scene.settings.width_chunk, 10);
+        assert_eq!(scene.settings.height_chunk, 20);
+        assert_eq!(scene.settings.seed, 3);
+        assert_eq!(scene.settings.samples_per_pixel, 9);
+        assert_eq!(scene.settings.pixel_filter, PixelFilter::Gaussian);
+        assert_approx_eq!(scene.settings.exposure, -1.);
+        assert_eq!(scene.settings.tone_mapping, ToneMapping::ExtendedReinhard{white_point: 2.});
+        assert_approx_eq!(scene.view.eye().y, 2.);
+        assert_eq!(scene.world.lights.len(), 2);
+        let white = scene.world.lights[0].sample(&Vector::new(), (0.5, 0.5)).unwrap().irradiance;
+        assert_approx_eq!(white.r, 0.4 / (4. * consts::PI * 300.));
+        assert_eq!(white.r, white.b);
+        let colored = scene.world.lights[1].sample(&Vector::new(), (0.5, 0.5)).unwrap().irradiance;
+        assert_approx_eq!(colored.r, 1000. / (4. * consts::PI * 100.));
+        assert_approx_eq!(colored.b, colored.r * 0.25);
+
+        let ray = Ray::new(&Vector::new(), &Vector{x: 0., y: 0., z: -1.});
+        let (item, hit) = scene.world.item_that_collide(&ray).unwrap();
+        assert_approx_eq!(hit.point.z, -4.);
+        assert_approx_eq!(item.color().r, 1.);
+        assert_approx_eq!(item.color().g, 0.);
+        assert!(!item.is_specular());
+
+        let ray = Ray::new(&Vector::new(), &Vector{x: 0., y: -10., z: -5.});
+        let (item, _) = scene.world.item_that_collide(&ray).unwrap();
+        assert_eq!(item.refractive_index(), Some(1.5));
+
+        let ray = Ray::new(&Vector::new(), &Vector{x: 4., y: 0., z: -8.});
+        let (item, hit) = scene.world.item_that_collide(&ray).unwrap();
+        assert_approx_eq!(hit.point.z, -8.);
+        assert_approx_eq!(item.color().b, 1.);
+
+        // Shapes with the same named material share the id, inline materials get their own
+        let material_ids: Vec<u32> = (0..5).map(|idx| scene.world.material_id(idx)).collect();
+        assert_eq!(material_ids, vec![1, 2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn load_mesh()
+    {
+        let directory = std::env::temp_dir().join(format!("nrtrt_scene_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("quad.obj"), "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\np 1\n").unwrap();
+        let scene = Scene::parse_in("mesh file quad.obj position -1 -1 -4 scale 2 color 0 0 1", &directory);
+        let glowing = Scene::parse_in("mesh file quad.obj position -1 -1 -4 scale 2 emission 5 5 5", &directory);
+        let missing = Scene::parse_in("mesh file missing.obj", &directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let scene = scene.unwrap();
+        assert_eq!(scene.warnings.len(), 1);
+        assert!(scene.warnings[0].contains("'p'"));
+        let ray = Ray::new(&Vector::new(), &Vector{x: 0.2, y: 0.2, z: -1.});
+        let (item, hit) = scene.world.item_that_collide(&ray).unwrap();
+        assert_approx_eq!(hit.point.z, -4.);
+        assert_approx_eq!(item.color().b, 1.);
+
+        // Emission alone replaces the material of the model too
+        let glowing = glowing.unwrap();
+        let (item, _) = glowing.world.item_that_collide(&ray).unwrap();
+        assert_approx_eq!(item.emission().g, 5.);
+
+        match missing
+        {
+            Err(SceneError::Parse{line, message}) => {
+                assert_eq!(line, 1);
+                assert!(message.contains("missing.obj"));
+            },
+            _ => panic!("missing mesh should be reported")
+        }
+    }
+
+    #[test]
+    fn report_line_of_error()
+    {
+        let (line, message) = get_error_line("camera position 0 0 0\n\nsphere position 0 0 0 radious 1\n");
+        assert_eq!(line, 3);
+        assert!(message.contains("radious"));
+
+        let (line, message) = get_error_line("sphere position 0 0 0 radius 1\nsphere position 0 0 radius 1");
+        assert_eq!(line, 2);
+        assert!(message.contains("'position'"));
+
+        let (line, message) = get_error_line("sphere position 0 0 0 radius 1 material glass");
+        assert_eq!(line, 1);
+        assert!(message.contains("glass"));
+
+        let (line, _) = get_error_line("# Comment\ncube position 0 0 0");
+        assert_eq!(line, 2);
+
+        let (line, _) = get_error_line("light position 0 0 0");
+        assert_eq!(line, 1);
+
+        let (line, _) = get_error_line("render width -400");
+        assert_eq!(line, 1);
+
+        let (line, message) = get_error_line("render tonemap filmic");
+        assert_eq!(line, 1);
+        assert!(message.contains("filmic"));
+
+        let (line, _) = get_error_line("render tonemap aces white 3");
+        assert_eq!(line, 1);
+
+        let (line, _) = get_error_line("material red color 2 0 0");
+        assert_eq!(line, 1);
+
+        let (line, message) = get_error_line("rectangle corner 0 0 0 edge1 1 0 0 edge2 2 0 0");
+        assert_eq!(line, 1);
+        assert!(message.contains("parallel"));
+
+        let (line, message) = get_error_line("triangle a 0 0 0 b 1 1 1 c 2 2 2");
+        assert_eq!(line, 1);
+        assert!(message.contains("one line"));
+
+        let (line, _) = get_error_line("camera position 0 0 5 target 0 0 5");
+        assert_eq!(line, 1);
+
+        let (line, message) = get_error_line("camera direction 0 -1 0");
+        assert_eq!(line, 1);
+        assert!(message.contains("parallel"));
+
+        let (line, _) = get_error_line("camera direction 0 0 -1 target 0 0 0");
+        assert_eq!(line, 1);
+
+        let (line, _) = get_error_line("render fov 60 vfov 40");
+        assert_eq!(line, 1);
+
+        let (line, message) = get_error_line("render projection fisheye extent 3");
+        assert_eq!(line, 1);
+        assert!(message.contains("orthographic"));
+
+        let (line, message) = get_error_line("render filter lanczos");
+        assert_eq!(line, 1);
+        assert!(message.contains("lanczos"));
+
+        let (line, message) = get_error_line("render projection cylindrical");
+        assert_eq!(line, 1);
+        assert!(message.contains("cylindrical"));
+
+        let (line, message) = get_error_line("camera direction 0 0 -1 aperture 0.1");
+        assert_eq!(line, 1);
+        assert!(message.contains("focus"));
+
+        let (line, message) = get_error_line("material red color 1 0 0\nmaterial mirror reflectivity 5");
+        assert_eq!(line, 2);
+        assert!(message.contains("0..1"));
+        let (line, _) = get_error_line("sphere position 0 0 0 radius 1 reflectivity -1");
+        assert_eq!(line, 1);
+
+        let (_, message) = get_error_line("sphere position 0 0 0 radius nan");
+        assert!(message.contains("finite"));
+        let (_, message) = get_error_line("sphere position 0 inf 0 radius 1");
+        assert!(message.contains("finite"));
+    }
+
+    #[test]
+    fn look_at_camera()
+    {
+        let scene = Scene::parse("
+            render vfov 40
+            camera position 10 0 0 target 0 0 0 up 0 -2 0
+        ").unwrap();
+        assert_eq!(scene.settings.fov_axis, FovAxis::Vertical);
+        assert_approx_eq!(scene.settings.fov, 40.);
+        assert_approx_eq!(scene.view.forward().x, -1.);
+        assert_approx_eq!(scene.view.up().y, -1.);
+        assert_approx_eq!(scene.view.right().z, 1.);
+        let camera = scene.camera().unwrap();
+        assert_approx_eq!(camera.view.eye().x, 10.);
+        assert_eq!(camera.lens.aperture_radius, 0.);
+
+        // Focused at the target, unless told otherwise
+        let scene = Scene::parse("camera position 0 0 5 target 0 0 -3 aperture 0.2").unwrap();
+        assert_approx_eq!(scene.lens.focus_distance, 8.);
+        assert_approx_eq!(scene.lens.aperture_radius, 0.2);
+        let scene = Scene::parse("camera position 0 0 5 target 0 0 -3 aperture 0.2 focus 3").unwrap();
+        assert_approx_eq!(scene.lens.focus_distance, 3.);
+    }
+
+    #[test]
+    fn projections()
+    {
+        let scene = Scene::parse("render projection orthographic extent 4").unwrap();
+        assert_eq!(scene.settings.projection, Projection::Orthographic{extent: 4.});
+        let scene = Scene::parse("render projection fisheye fov 220").unwrap();
+        assert_eq!(scene.settings.projection, Projection::Fisheye);
+        assert!(scene.camera().is_ok());
+        let scene = Scene::parse("render projection equirectangular width 400 height 200").unwrap();
+        assert_eq!(scene.settings.projection, Projection::Equirectangular);
+    }
+
+    #[test]
+    fn area_lights()
+    {
+        let scene = Scene::parse("
+            light position 0 -4 -6 intensity 300 shape sphere radius 0.5
+            light position 0 -4 -6 intensity 300 shape rectangle edge1 1 0 0 edge2 0 0 2 color 1 0.9 0.8
+            light position 0 -4 -6 intensity 300 shape disk normal 0 2 0 radius 1
+            light position 0 -4 -6 intensity 300
+        ").unwrap();
+        // Rays going up from the floor towards the lights
+        let hits = |x: f64, z: f64| -> Vec<Option<f64>> {
+            let ray = Ray::new(&Vector{x, y: 0., z}, &Vector{x: 0., y: -1., z: 0.});
+            scene.world.lights.iter().map(|light| light.intersect(&ray)).collect()
+        };
+        let center = hits(0., -6.);
+        assert_approx_eq!(center[0].unwrap(), 3.5);
+        assert_approx_eq!(center[1].unwrap(), 4.);
+        assert_approx_eq!(center[2].unwrap(), 4.);
+        assert!(center[3].is_none());
+        // Inside of the rectangle, outside of the disk
+        let corner = hits(0.45, -6.95);
+        assert!(corner[0].is_none() && corner[1].is_some() && corner[2].is_none());
+
+        let (_, message) = get_error_line("light position 0 0 0 intensity 1 shape tube radius 1");
+        assert!(message.contains("unknown light shape"));
+        let (_, message) = get_error_line("light position 0 0 0 intensity 1 shape sphere");
+        assert!(message.contains("radius"));
+        let (_, message) = get_error_line("light position 0 0 0 intensity 1 shape sphere radius 1 normal 0 1 0");
+        assert!(message.contains("doesn't belong"));
+        let (_, message) = get_error_line("light position 0 0 0 intensity 1 radius 1");
+        assert!(message.contains("doesn't belong"));
+        let (_, message) = get_error_line("light position 0 0 0 intensity 1 shape rectangle edge1 1 0 0 edge2 2 0 0");
+        assert!(message.contains("parallel"));
+    }
+
+    #[test]
+    fn emissive_materials()
+    {
+        let scene = Scene::parse("
+            material neon color 1 0.2 0.6 emission 5 1 3
+            sphere position 0 0 -5 radius 1 material neon
+            rectangle corner -1 -4 -6 edge1 2 0 0 edge2 0 0 2 color 1 1 1 emission 8 8 8
+            sphere position 0 0 5 radius 1 color 1 1 1
+        ").unwrap();
+        let ray = Ray::new(&Vector::new(), &Vector{x: 0., y: 0., z: -1.});
+        let (neon, _) = scene.world.item_that_collide(&ray).unwrap();
+        assert_approx_eq!(neon.emission().r, 5.);
+        assert_approx_eq!(neon.emission().b, 3.);
+        let (panel, _) = scene.world.item_that_collide(&Ray::new(&Vector{x: 0., y: -2., z: -5.}, &Vector{x: 0., y: -1., z: 0.})).unwrap();
+        assert_approx_eq!(panel.emission().g, 8.);
+        let (plain, _) = scene.world.item_that_collide(&Ray::new(&Vector::new(), &Vector{x: 0., y: 0., z: 1.})).unwrap();
+        assert_eq!(plain.emission().r, 0.);
+
+        let (_, message) = get_error_line("sphere position 0 0 0 radius 1 emission 1 -1 1");
+        assert!(message.contains("negative"));
+        let (_, message) = get_error_line("material neon emission 1 1 1\nsphere position 0 0 0 radius 1 material neon emission 1 1 1");
+        assert!(message.contains("both"));
+    }
+
+    #[test]
+    fn shapes_take_material_properties()
+    {
+        let tokens = ["color", "1", "1", "1", "reflectivity", "0.5", "specular", "true", "ior", "1.5", "emission", "1", "1", "1"];
+        for shape in &[SPHERE_PROPERTIES, RECTANGLE_PROPERTIES, TRIANGLE_PROPERTIES, MESH_PROPERTIES]
+        {
+            let properties = Properties::parse_shape(1, "shape", &tokens, shape).unwrap();
+            assert!(MATERIAL_PROPERTIES.iter().all(|(name, _)| properties.has(name)));
+        }
+    }
+
+    #[test]
+    fn sun_and_spot()
+    {
+        let scene = Scene::parse("
+            sun direction 0 2 0 intensity 1.5 color 1 0.5 0.5 diameter 0.53
+            spot position 0 -4 0 target 0 0 0 intensity 100 angle 30 falloff 5
+        ").unwrap();
+        let sun = scene.world.lights[0].sample(&Vector{x: 7., y: 1., z: -3.}, (0., 0.)).unwrap();
+        assert_approx_eq!(sun.direction.y, -1.);
+        assert_approx_eq!(sun.irradiance.r, 1.5);
+        assert_approx_eq!(sun.irradiance.g, 0.75);
+        let spot = &scene.world.lights[1];
+        assert_approx_eq!(spot.sample(&Vector::new(), (0.5, 0.5)).unwrap().irradiance.r, 100. / (4. * consts::PI * 16.));
+        assert!(spot.sample(&Vector{x: 4., y: 0., z: 0.}, (0.5, 0.5)).is_none());
+
+        // Negative intensities would take the light away
+        let (line, message) = get_error_line("light position 0 -5 0 intensity 100\nlight position 0 0 0 intensity -300");
+        assert_eq!(line, 2);
+        assert!(message.contains("negative"));
+        let (line, message) = get_error_line("sun direction 0 1 0 intensity -1");
+        assert_eq!(line, 1);
+        assert!(message.contains("intensity"));
+        let (line, _) = get_error_line("spot position 0 0 0 direction 0 1 0 intensity -1 angle 30");
+        assert_eq!(line, 1);
+
+        let (_, message) = get_error_line("sun direction 0 0 0 intensity 1");
+        assert!(message.contains("direction"));
+        let (_, message) = get_error_line("sun direction 0 1 0 intensity 1 diameter 200");
+        assert!(message.contains("diameter"));
+        let (_, message) = get_error_line("spot position 0 0 0 intensity 1 angle 30");
+        assert!(message.contains("direction or a target"));
+        let (_, message) = get_error_line("spot position 0 0 0 direction 0 1 0 intensity 1 angle 30 falloff 40");
+        assert!(message.contains("falloff"));
+        let (_, message) = get_error_line("spot position 0 0 0 direction 0 1 0 intensity 1");
+        assert!(message.contains("angle"));
+    }
+}
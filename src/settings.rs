@@ -0,0 +1,255 @@
+use crate::tonemap::{ToneMapper, ToneMapping};
+use crate::view::Projection;
+use crate::filter::PixelFilter;
+use std::{error, fmt};
+
+#[derive(Debug, PartialEq)]
+pub enum SettingsError
+{
+    EmptyImage,
+    EmptyChunk,
+    NoSamples,
+    // Image dimension (width or height) that cannot be split into whole chunks
+    ChunkDoesNotDivide{dimension: &'static str, size: u32, chunk_size: u32},
+    InvalidFov(f64),
+    InvalidFisheyeFov(f64),
+    InvalidExtent(f64),
+    InvalidFalloff(f64),
+    InvalidExposure(f64),
+    InvalidWhitePoint(f64),
+}
+
+impl fmt::Display for SettingsError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            SettingsError::EmptyImage => write!(f, "width and height of the image have to be greater than 0"),
+            SettingsError::EmptyChunk => write!(f, "width and height of the chunk have to be greater than 0"),
+            SettingsError::NoSamples => write!(f, "every pixel needs at least one sample"),
+            SettingsError::ChunkDoesNotDivide{dimension, size, chunk_size} =>
+                write!(f, "image {} {} is not divisible by the chunk {} {}", dimension, size, dimension, chunk_size),
+            SettingsError::InvalidFov(fov) => write!(f, "field of view has to be between 0 and 180 degrees, got {}", fov),
+            SettingsError::InvalidFisheyeFov(fov) => write!(f, "field of view of the fisheye has to be between 0 and 360 degrees, got {}", fov),
+            SettingsError::InvalidExtent(extent) => write!(f, "extent of the orthographic projection has to be positive, got {}", extent),
+            SettingsError::InvalidFalloff(falloff) => write!(f, "scattered rays falloff has to be positive, got {}", falloff),
+            SettingsError::InvalidExposure(exposure) => write!(f, "exposure has to be a finite number, got {}", exposure),
+            SettingsError::InvalidWhitePoint(white_point) => write!(f, "white point has to be positive, got {}", white_point),
+        }
+    }
+}
+
+impl error::Error for SettingsError {}
+
+// Which side of the image the field of view spans
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FovAxis
+{
+    Horizontal,
+    Vertical,
+}
+
+// Everything that controls the quality and the size of the render
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings
+{
+    pub width: u32,
+    pub height: u32,
+    // Field of view in degrees along the fov_axis, the other one follows from the aspect ratio of the image
+    pub fov: f64,
+    pub fov_axis: FovAxis,
+    pub projection: Projection,
+    // Primary rays per pixel, combined by the pixel filter. One sample goes through the center of the pixel.
+    pub samples_per_pixel: u32,
+    pub pixel_filter: PixelFilter,
+    pub max_ray_depth: u32,
+    pub num_of_reflected_rays: usize,
+    // How many rays should be send in each reflection
+    // i.e. 0.75 -> first reflection will have 0.75 * num_of_reflected_rays,
+    // second will have 0.75 of previous number and so on
+    pub scattered_rays_falloff: f64,
+    // Size of the chunks that the image is split into
+    pub width_chunk: u32,
+    pub height_chunk: u32,
+    // Seed of all random numbers used in the render
+    pub seed: u64,
+    // Brightness correction in stops applied before the tone mapping
+    pub exposure: f64,
+    pub tone_mapping: ToneMapping,
+}
+
+impl Default for RenderSettings
+{
+    fn default() -> RenderSettings
+    {
+        RenderSettings::new()
+    }
+}
+
+impl RenderSettings
+{
+    pub fn new() -> RenderSettings
+    {
+        RenderSettings{
+            width: 400,
+            height: 300,
+            fov: 70.,
+            fov_axis: FovAxis::Horizontal,
+            projection: Projection::Perspective,
+            samples_per_pixel: 1,
+            pixel_filter: PixelFilter::Box,
+            max_ray_depth: 4,
+            num_of_reflected_rays: 200,
+            scattered_rays_falloff: 0.75,
+            width_chunk: 20,
+            height_chunk: 20,
+            seed: 0,
+            exposure: 0.,
+            tone_mapping: ToneMapping::Clamp,
+        }
+    }
+
+    // Checks if the image can be rendered with these settings
+    pub fn validate(&self) -> Result<(), SettingsError>
+    {
+        if self.width == 0 || self.height == 0
+        {
+            return Err(SettingsError::EmptyImage);
+        }
+        if self.width_chunk == 0 || self.height_chunk == 0
+        {
+            return Err(SettingsError::EmptyChunk);
+        }
+        if self.samples_per_pixel == 0
+        {
+            return Err(SettingsError::NoSamples);
+        }
+        // Otherwise the pixels on the right and bottom edge would never be rendered
+        if !self.width.is_multiple_of(self.width_chunk)
+        {
+            return Err(SettingsError::ChunkDoesNotDivide{dimension: "width", size: self.width, chunk_size: self.width_chunk});
+        }
+        if !self.height.is_multiple_of(self.height_chunk)
+        {
+            return Err(SettingsError::ChunkDoesNotDivide{dimension: "height", size: self.height, chunk_size: self.height_chunk});
+        }
+        match self.projection
+        {
+            Projection::Perspective => if self.fov.is_nan() || self.fov <= 0. || self.fov >= 180.
+            {
+                return Err(SettingsError::InvalidFov(self.fov));
+            },
+            Projection::Fisheye => if self.fov.is_nan() || self.fov <= 0. || self.fov > 360.
+            {
+                return Err(SettingsError::InvalidFisheyeFov(self.fov));
+            },
+            Projection::Orthographic{extent} => if extent.is_nan() || extent <= 0. || extent.is_infinite()
+            {
+                return Err(SettingsError::InvalidExtent(extent));
+            },
+            // Always shows everything around the camera
+            Projection::Equirectangular => ()
+        }
+        if self.scattered_rays_falloff.is_nan() || self.scattered_rays_falloff <= 0.
+        {
+            return Err(SettingsError::InvalidFalloff(self.scattered_rays_falloff));
+        }
+        if !self.exposure.is_finite()
+        {
+            return Err(SettingsError::InvalidExposure(self.exposure));
+        }
+        if let ToneMapping::ExtendedReinhard{white_point} = self.tone_mapping
+        {
+            if white_point.is_nan() || white_point <= 0.
+            {
+                return Err(SettingsError::InvalidWhitePoint(white_point));
+            }
+        }
+        Ok(())
+    }
+
+    // Converts the rendered radiance to the displayed colors
+    pub fn tone_mapper(&self) -> ToneMapper
+    {
+        ToneMapper::new(self.exposure, self.tone_mapping)
+    }
+
+    pub fn num_of_chunks(&self) -> u32
+    {
+        self.chunks_per_row() * (self.height / self.height_chunk)
+    }
+
+    pub fn chunks_per_row(&self) -> u32
+    {
+        self.width / self.width_chunk
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::settings::{RenderSettings, SettingsError};
+    use crate::tonemap::ToneMapping;
+    use crate::view::Projection;
+
+    #[test]
+    fn default_settings_are_valid()
+    {
+        let settings = RenderSettings::new();
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(settings.num_of_chunks(), 20 * 15);
+    }
+
+    #[test]
+    fn reject_invalid_settings()
+    {
+        let mut settings = RenderSettings::new();
+        settings.width = 410;
+        assert_eq!(settings.validate(), Err(SettingsError::ChunkDoesNotDivide{dimension: "width", size: 410, chunk_size: 20}));
+
+        let mut settings = RenderSettings::new();
+        settings.height_chunk = 7;
+        assert_eq!(settings.validate(), Err(SettingsError::ChunkDoesNotDivide{dimension: "height", size: 300, chunk_size: 7}));
+
+        let mut settings = RenderSettings::new();
+        settings.height = 0;
+        assert_eq!(settings.validate(), Err(SettingsError::EmptyImage));
+
+        let mut settings = RenderSettings::new();
+        settings.width_chunk = 0;
+        assert_eq!(settings.validate(), Err(SettingsError::EmptyChunk));
+
+        let mut settings = RenderSettings::new();
+        settings.samples_per_pixel = 0;
+        assert_eq!(settings.validate(), Err(SettingsError::NoSamples));
+
+        let mut settings = RenderSettings::new();
+        settings.fov = 180.;
+        assert_eq!(settings.validate(), Err(SettingsError::InvalidFov(180.)));
+
+        let mut settings = RenderSettings::new();
+        settings.projection = Projection::Fisheye;
+        settings.fov = 180.;
+        assert_eq!(settings.validate(), Ok(()));
+        settings.fov = 400.;
+        assert_eq!(settings.validate(), Err(SettingsError::InvalidFisheyeFov(400.)));
+
+        let mut settings = RenderSettings::new();
+        settings.projection = Projection::Orthographic{extent: 0.};
+        settings.fov = 180.;
+        assert_eq!(settings.validate(), Err(SettingsError::InvalidExtent(0.)));
+
+        let mut settings = RenderSettings::new();
+        settings.scattered_rays_falloff = 0.;
+        assert_eq!(settings.validate(), Err(SettingsError::InvalidFalloff(0.)));
+
+        let mut settings = RenderSettings::new();
+        settings.exposure = f64::INFINITY;
+        assert_eq!(settings.validate(), Err(SettingsError::InvalidExposure(f64::INFINITY)));
+
+        let mut settings = RenderSettings::new();
+        settings.tone_mapping = ToneMapping::ExtendedReinhard{white_point: -1.};
+        assert_eq!(settings.validate(), Err(SettingsError::InvalidWhitePoint(-1.)));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::ray::Ray;
+use crate::vector::Vector;
+use crate::shapes::{Collision, Hit, MaterialTrait, Shape};
+use crate::shapes::triangle::{intersect_triangle, barycentric_coordinates};
+use crate::material::Material;
+use crate::pixel::Color;
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use std::sync::Arc;
+use std::f64;
+
+// Vertex data of one or more meshes. Triangles refer to it by indices.
+#[derive(Clone, Debug, Default)]
+pub struct MeshBuffers
+{
+    pub positions: Vec<Vector>,
+    pub normals: Vec<Vector>,
+    // Texture coordinates (u, v)
+    pub uvs: Vec<(f64, f64)>
+}
+
+// Triangle of the mesh, described by indices into the vertex buffers of the mesh
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshTriangle
+{
+    pub positions: [usize; 3],
+    // Per vertex normals used for smooth shading, flat normal of the triangle is used without them
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>
+}
+
+// Many triangles that share vertices and the material. Vertex buffers are reference counted,
+// so many meshes (i.e. groups of one model) can use the same buffers.
+pub struct TriangleMesh
+{
+    pub buffers: Arc<MeshBuffers>,
+    pub triangles: Vec<MeshTriangle>,
+    pub material: Material,
+    bounds: Aabb,
+    // Hierarchy over the triangles of the mesh
+    bvh: Bvh
+}
+
+impl TriangleMesh
+{
+    // Panics if any triangle uses a vertex that is not in the buffers
+    pub fn new(buffers: Arc<MeshBuffers>, triangles: Vec<MeshTriangle>, material: &Material) -> TriangleMesh
+    {
+        let in_range = |indices: Option<[usize; 3]>, len: usize| indices.is_none_or(|indices| indices.iter().all(|idx| *idx < len));
+        for triangle in triangles.iter()
+        {
+            assert!(in_range(Some(triangle.positions), buffers.positions.len()), "position index out of range");
+            assert!(in_range(triangle.normals, buffers.normals.len()), "normal index out of range");
+            assert!(in_range(triangle.uvs, buffers.uvs.len()), "texture coordinate index out of range");
+        }
+        let triangle_bounds: Vec<Aabb> = triangles.iter().map(|triangle| {
+            let [a, b, c] = triangle.positions;
+            let positions = &buffers.positions;
+            Aabb::new(&positions[a], &positions[a]).grow(&positions[b]).grow(&positions[c])
+        }).collect();
+        let bounds = triangle_bounds.iter().fold(Aabb::empty(), |bounds, triangle| bounds.union(triangle));
+        TriangleMesh{
+            bvh: Bvh::build(&triangle_bounds),
+            bounds,
+            buffers,
+            triangles,
+            material: *material
+        }
+    }
+
+    fn vertices(&self, triangle: &MeshTriangle) -> (&Vector, &Vector, &Vector)
+    {
+        let [a, b, c] = triangle.positions;
+        let positions = &self.buffers.positions;
+        (&positions[a], &positions[b], &positions[c])
+    }
+
+    // Normal at the barycentric coordinates (u, v) of the triangle, interpolated from the vertices
+    // or the flat one when they have no normals
+    fn normal_in_triangle(&self, triangle: &MeshTriangle, u: f64, v: f64) -> Vector
+    {
+        match triangle.normals
+        {
+            Some([a, b, c]) => {
+                let normals = &self.buffers.normals;
+                (normals[a] * (1. - u - v) + normals[b] * u + normals[c] * v).normalized()
+            },
+            None => {
+                let (a, b, c) = self.vertices(triangle);
+                ((*b - *a) * (*c - *a)).normalized()
+            }
+        }
+    }
+
+    // Texture coordinates at the barycentric coordinates (u, v) of the triangle, if it has them
+    fn uv_in_triangle(&self, triangle: &MeshTriangle, u: f64, v: f64) -> Option<(f64, f64)>
+    {
+        let [a, b, c] = triangle.uvs?;
+        let uvs = &self.buffers.uvs;
+        Some((uvs[a].0 * (1. - u - v) + uvs[b].0 * u + uvs[c].0 * v,
+              uvs[a].1 * (1. - u - v) + uvs[b].1 * u + uvs[c].1 * v))
+    }
+
+    // Finds the triangle that the point lies on and the barycentric coordinates of the point in it.
+    // Hits of the rays already know their triangle, this is only for the points from elsewhere.
+    fn find_triangle(&self, point: &Vector) -> Option<(&MeshTriangle, f64, f64)>
+    {
+        // Collision points are calculated from the ray, so they are not exactly on the triangle
+        let tolerance = 1e-6 * (1. + (self.bounds.max - self.bounds.min).distance());
+        let mut best: Option<(usize, f64, f64)> = None;
+        let mut smallest_error = tolerance;
+        self.bvh.visit_containing(point, tolerance, |idx| {
+            let (a, b, c) = self.vertices(&self.triangles[idx]);
+            let (u, v, distance) = barycentric_coordinates(a, b, c, point);
+            // How far outside of the triangle the point is, scaled by the size of the triangle
+            let outside = (-u).max(-v).max(u + v - 1.).max(0.) * (*b - *a).distance().max((*c - *a).distance());
+            let error = distance + outside;
+            if error <= smallest_error
+            {
+                smallest_error = error;
+                best = Some((idx, u, v));
+            }
+        });
+        best.map(|(idx, u, v)| (&self.triangles[idx], u, v))
+    }
+
+    // Interpolated texture coordinates at the point of the mesh, if its triangle has them
+    pub fn uv_at_point(&self, point: &Vector) -> Option<(f64, f64)>
+    {
+        let (triangle, u, v) = self.find_triangle(point)?;
+        self.uv_in_triangle(triangle, u, v)
+    }
+}
+
+impl MaterialTrait for TriangleMesh
+{
+    fn reflectivity(&self) -> f64 {
+        self.material.reflectivity
+    }
+
+    fn color(&self) -> Color {
+        self.material.color
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular
+    }
+
+    fn refractive_index(&self) -> Option<f64> {
+        self.material.refractive_index
+    }
+
+    fn emission(&self) -> Color {
+        self.material.emission
+    }
+}
+
+impl Collision for TriangleMesh
+{
+    fn can_collide(&self, ray: &Ray) -> bool
+    {
+        self.bounds.intersect(ray, f64::MAX).is_some()
+    }
+    fn collision_point(&self, ray: &Ray) -> Option<Vector>
+    {
+        self.collision(ray).map(|hit| hit.point)
+    }
+    fn normal_at_point(&self, point: &Vector) -> Option<Vector>
+    {
+        let (triangle, u, v) = self.find_triangle(point)?;
+        Some(self.normal_in_triangle(triangle, u, v))
+    }
+    // The hit remembers its triangle and where in it the ray hits
+    fn collision(&self, ray: &Ray) -> Option<Hit>
+    {
+        let direction_length = ray.direction.distance();
+        let (idx, _, (parameter, u, v)) = self.bvh.closest_hit(ray, f64::MAX, |idx| {
+            let (a, b, c) = self.vertices(&self.triangles[idx]);
+            intersect_triangle(a, b, c, ray).map(|(parameter, u, v)| (parameter * direction_length, (parameter, u, v)))
+        })?;
+        let triangle = &self.triangles[idx];
+        Some(Hit{
+            point: ray.start_position + ray.direction * parameter,
+            normal: self.normal_in_triangle(triangle, u, v),
+            uv: self.uv_in_triangle(triangle, u, v)
+        })
+    }
+    fn up_direction(&self) -> Vector
+    {
+        Vector{
+            x: 0.,
+            y: -1.,
+            z: 0.
+        }
+    }
+    fn position(&self) -> Vector
+    {
+        self.bounds.centroid()
+    }
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        // Axis aligned flat meshes have flat boxes, give them some thickness
+        let padding = Vector{x: 1e-9, y: 1e-9, z: 1e-9};
+        Some(Aabb::new(&(self.bounds.min - padding), &(self.bounds.max + padding)))
+    }
+}
+
+impl Shape for TriangleMesh
+{}
+
+#[cfg(test)]
+mod test
+{
+    use crate::vector::Vector;
+    use crate::shapes::{TriangleMesh, MeshTriangle, MeshBuffers, Collision};
+    use crate::ray::Ray;
+    use crate::material::Material;
+    use assert_approx_eq::assert_approx_eq;
+    use std::sync::Arc;
+
+    // Square in the z = 0 plane made of two triangles, with normals tilted towards +x and -x on the sides
+    fn get_mesh(smooth: bool) -> TriangleMesh
+    {
+        let buffers = Arc::new(MeshBuffers{
+            positions: vec![Vector{x: 0., y: 0., z: 0.},
+                            Vector{x: 2., y: 0., z: 0.},
+                            Vector{x: 2., y: 2., z: 0.},
+                            Vector{x: 0., y: 2., z: 0.}],
+            normals: vec![Vector{x: -1., y: 0., z: 1.}.normalized(),
+                          Vector{x: 1., y: 0., z: 1.}.normalized()],
+            uvs: vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)]
+        });
+        let triangles = vec![MeshTriangle{positions: [0, 1, 2], normals: if smooth {Some([0, 1, 1])} else {None}, uvs: Some([0, 1, 2])},
+                             MeshTriangle{positions: [0, 2, 3], normals: if smooth {Some([0, 1, 0])} else {None}, uvs: Some([0, 2, 3])}];
+        TriangleMesh::new(buffers, triangles, &Material::default())
+    }
+
+    #[test]
+    fn collision_point()
+    {
+        let mesh = get_mesh(false);
+        let ray = Ray::new(&Vector{x: 1.5, y: 0.5, z: 3.}, &Vector{x: 0., y: 0., z: -1.});
+        assert!(mesh.can_collide(&ray));
+        let collision_point = mesh.collision_point(&ray).unwrap();
+        assert_approx_eq!(collision_point.x, 1.5);
+        assert_approx_eq!(collision_point.z, 0.);
+
+        let ray = Ray::new(&Vector{x: 0.5, y: 1.5, z: 3.}, &Vector{x: 0., y: 0., z: -1.});
+        assert_approx_eq!(mesh.collision_point(&ray).unwrap().y, 1.5);
+
+        let ray = Ray::new(&Vector{x: 2.5, y: 1.5, z: 3.}, &Vector{x: 0., y: 0., z: -1.});
+        assert!(mesh.collision_point(&ray).is_none());
+    }
+
+    #[test]
+    fn flat_normal()
+    {
+        let mesh = get_mesh(false);
+        let normal = mesh.normal_at_point(&Vector{x: 1.5, y: 0.5, z: 0.}).unwrap();
+        assert_approx_eq!(normal.x, 0.);
+        assert_approx_eq!(normal.z, 1.);
+        // Point that is not on the mesh
+        assert!(mesh.normal_at_point(&Vector{x: 1.5, y: 0.5, z: 1.}).is_none());
+    }
+
+    #[test]
+    fn smooth_normal()
+    {
+        let mesh = get_mesh(true);
+        // Halfway between the left and right side the normals cancel out in x
+        let ray = Ray::new(&Vector{x: 1., y: 0.5, z: 3.}, &Vector{x: 0., y: 0., z: -1.});
+        let normal = mesh.normal_at_point(&mesh.collision_point(&ray).unwrap()).unwrap();
+        assert_approx_eq!(normal.x, 0.);
+        assert_approx_eq!(normal.distance(), 1.);
+
+        let ray = Ray::new(&Vector{x: 1.9, y: 1.5, z: 3.}, &Vector{x: 0., y: 0., z: -1.});
+        let normal = mesh.normal_at_point(&mesh.collision_point(&ray).unwrap()).unwrap();
+        assert!(normal.x > 0.5);
+    }
+
+    #[test]
+    fn hits_know_their_triangle()
+    {
+        let mesh = get_mesh(true);
+        let ray = Ray::new(&Vector{x: 1.5, y: 0.5, z: 3.}, &Vector{x: 0., y: 0., z: -1.});
+        let hit = mesh.collision(&ray).unwrap();
+        assert_approx_eq!(hit.point.x, 1.5);
+        let (u, v) = hit.uv.unwrap();
+        assert_approx_eq!(u, 0.75);
+        assert_approx_eq!(v, 0.25);
+        let normal = mesh.normal_at_point(&hit.point).unwrap();
+        assert_approx_eq!(hit.normal.x, normal.x);
+        assert_approx_eq!(hit.normal.z, normal.z);
+
+        // From far away the point is too imprecise to find its triangle again, the hit still has the normal
+        let buffers = Arc::new(MeshBuffers{
+            positions: vec![Vector{x: 0., y: 0., z: 0.}, Vector{x: 2., y: 0., z: 1.}, Vector{x: 0., y: 2., z: 0.5}],
+            ..MeshBuffers::default()
+        });
+        let tilted = TriangleMesh::new(buffers, vec![MeshTriangle{positions: [0, 1, 2], normals: None, uvs: None}], &Material::default());
+        let direction = Vector{x: 0.31, y: 0.17, z: -0.73};
+        let ray = Ray::new(&(Vector{x: 0.3, y: 0.7, z: 0.325} - direction * 1e12), &direction);
+        let hit = tilted.collision(&ray).unwrap();
+        assert!(tilted.normal_at_point(&hit.point).is_none());
+        assert_approx_eq!(hit.normal.dot(Vector{x: -0.5, y: -0.25, z: 1.}.normalized()), 1.);
+        assert!(hit.uv.is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn reject_invalid_indices()
+    {
+        let buffers = Arc::new(MeshBuffers{positions: vec![Vector::new(); 2], ..MeshBuffers::default()});
+        TriangleMesh::new(buffers, vec![MeshTriangle{positions: [0, 1, 2], normals: None, uvs: None}], &Material::default());
+    }
+
+    #[test]
+    fn texture_coordinates()
+    {
+        let mesh = get_mesh(false);
+        let (u, v) = mesh.uv_at_point(&Vector{x: 1.5, y: 0.5, z: 0.}).unwrap();
+        assert_approx_eq!(u, 0.75);
+        assert_approx_eq!(v, 0.25);
+        let (u, v) = mesh.uv_at_point(&Vector{x: 0.5, y: 1.5, z: 0.}).unwrap();
+        assert_approx_eq!(u, 0.25);
+        assert_approx_eq!(v, 0.75);
+    }
+}
@@ -1,7 +1,11 @@
-mod shape;
-mod sphere;
-mod rectangle;
-
-pub use shape::{Collision, MaterialTrait, Shape};
-pub use sphere::Sphere;
-pub use rectangle::Rectangle;
+mod shape;
+mod sphere;
+mod rectangle;
+pub mod triangle;
+mod mesh;
+
+pub use shape::{Collision, Hit, MaterialTrait, Shape};
+pub use sphere::Sphere;
+pub use rectangle::Rectangle;
+pub use triangle::Triangle;
+pub use mesh::{TriangleMesh, MeshTriangle, MeshBuffers};
@@ -3,24 +3,52 @@ use crate::vector::Vector;
 use crate::shapes::{Collision, MaterialTrait, Shape};
 use crate::material::Material;
 use crate::pixel::Color;
+use crate::aabb::Aabb;
 
+// Parallelogram spanned by two edges starting at the same corner. The front face is the one
+// that the normal (first_edge x second_edge) points to.
 pub struct Rectangle
 {
-    pub first_corner: Vector,
-    pub dimensions: (f64, f64),
+    pub corner: Vector,
+    pub first_edge: Vector,
+    pub second_edge: Vector,
     pub material: Material
 }
 
 impl Rectangle
 {
-    pub fn new(first_corner: &Vector, dimensions: &(f64, f64), material: &Material) -> Rectangle
+    pub fn new(corner: &Vector, first_edge: &Vector, second_edge: &Vector, material: &Material) -> Rectangle
     {
         Rectangle{
-            first_corner: *first_corner,
-            dimensions: *dimensions,
+            corner: *corner,
+            first_edge: *first_edge,
+            second_edge: *second_edge,
             material: *material
         }
-        
+    }
+
+    // Not normalized normal of the plane of the rectangle
+    fn plane_normal(&self) -> Vector
+    {
+        self.first_edge * self.second_edge
+    }
+
+    // Returns the parameter of the ray in which it crosses the plane of the rectangle (if it does so in front of the start)
+    fn plane_parameter(&self, ray: &Ray) -> Option<f64>
+    {
+        let normal = self.plane_normal();
+        let denominator = normal.dot(ray.direction);
+        // Ray is parallel to the plane
+        if denominator.abs() < 1e-12
+        {
+            return None;
+        }
+        let parameter = normal.dot(self.corner - ray.start_position) / denominator;
+        if parameter > 0.00001
+        {
+            return Some(parameter);
+        }
+        None
     }
 }
 
@@ -37,55 +65,62 @@ impl MaterialTrait for Rectangle
     fn is_specular(&self) -> bool {
         self.material.is_specular
     }
+
+    fn refractive_index(&self) -> Option<f64> {
+        self.material.refractive_index
+    }
+
+    fn emission(&self) -> Color {
+        self.material.emission
+    }
 }
 
 impl Collision for Rectangle
 {
     fn can_collide(&self, ray: &Ray) -> bool
     {
-        let y_difference = self.first_corner.y - ray.start_position.y;
-        if ray.direction.y == 0.
-        {
-            return false
-        }
-        let parameter = y_difference / ray.direction.y;
-        return parameter > 0.00001;
+        self.plane_parameter(ray).is_some()
     }
     fn collision_point(&self, ray: &Ray) -> Option<Vector>
     {
-        if self.can_collide(ray) == true
+        let parameter = self.plane_parameter(ray)?;
+        let result_position = ray.start_position + ray.direction * parameter;
+        // Coordinates of the point along both edges, for points inside both are in 0..1 range.
+        // Calculated with cross products, so the edges don't have to be perpendicular
+        let normal = self.plane_normal();
+        let normal_length_squared = normal.dot(normal);
+        let corner_to_point = result_position - self.corner;
+        let first = (corner_to_point * self.second_edge).dot(normal) / normal_length_squared;
+        let second = (self.first_edge * corner_to_point).dot(normal) / normal_length_squared;
+        if (0. ..=1.).contains(&first) && (0. ..=1.).contains(&second)
         {
-            let y_difference = self.first_corner.y - ray.start_position.y;
-            let parameter = y_difference / ray.direction.y;
-            let result_position = ray.start_position + ray.direction * parameter;
-            // println!("{:?}", result_position);
             return Some(result_position);
         }
-        else
-        {
-            return None;
-        }
+        None
     }
     fn normal_at_point(&self, _point: &Vector) -> Option<Vector>
     {
-        return Some(Vector{
-            x: 0.,
-            y: -1.,
-            z: 0.
-        });
+        Some(self.plane_normal().normalized())
     }
 
     fn up_direction(&self) -> Vector
     {
-        return Vector{
-            x: 0.,
-            y: -1.,
-            z: 0.
-        };
+        self.plane_normal().normalized()
     }
     fn position(&self) -> Vector
     {
-        return self.first_corner;
+        self.corner + (self.first_edge + self.second_edge) * 0.5
+    }
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        let opposite_corner = self.corner + self.first_edge + self.second_edge;
+        let bounding_box = Aabb::new(&self.corner, &self.corner)
+            .grow(&(self.corner + self.first_edge))
+            .grow(&(self.corner + self.second_edge))
+            .grow(&opposite_corner);
+        // Axis aligned rectangles have flat boxes, give them some thickness
+        let padding = Vector{x: 1e-9, y: 1e-9, z: 1e-9};
+        Some(Aabb::new(&(bounding_box.min - padding), &(bounding_box.max + padding)))
     }
 }
 
@@ -105,7 +140,10 @@ mod test
 
     fn get_rectangle() -> Rectangle
     {
-        Rectangle::new(&Vector{x: 0., y:0., z:0.,}, &(100., 100.), &Material::default())
+        Rectangle::new(&Vector{x: 0., y:0., z:0.,},
+                       &Vector{x: 100., y: 0., z: 0.},
+                       &Vector{x: 0., y: 0., z: 100.},
+                       &Material::default())
     }
     fn get_ray() -> Ray
     {
@@ -126,11 +164,11 @@ mod test
     fn can_collide() {
         let rect = get_rectangle();
         let mut ray = get_ray();
-        assert_eq!(rect.can_collide(&ray), true);
+        assert!(rect.can_collide(&ray));
         ray.direction.y = 1.;
-        assert_eq!(rect.can_collide(&ray), false);
+        assert!(!rect.can_collide(&ray));
         ray.start_position.y = -10.;
-        assert_eq!(rect.can_collide(&ray), true);
+        assert!(rect.can_collide(&ray));
     }
 
     #[test]
@@ -142,4 +180,42 @@ mod test
         assert_approx_eq!(collision_point.y, 0.);
         assert_approx_eq!(collision_point.z, 40.);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn miss_outside_of_the_edges(){
+        let rect = get_rectangle();
+        // Would hit the plane in (-10, 0, 10)
+        let ray = Ray::new(&Vector{x: -10., y: 10., z: 10.}, &Vector{x: 0., y: -1., z: 0.});
+        assert!(rect.can_collide(&ray));
+        assert!(rect.collision_point(&ray).is_none());
+        // Would hit the plane in (50, 0, 101)
+        let ray = Ray::new(&Vector{x: 50., y: 10., z: 101.}, &Vector{x: 0., y: -1., z: 0.});
+        assert!(rect.collision_point(&ray).is_none());
+    }
+
+    #[test]
+    fn oriented_rectangle(){
+        // Wall tilted by 45 degrees, with edges that are not perpendicular
+        let rect = Rectangle::new(&Vector{x: 0., y: 0., z: 0.},
+                                  &Vector{x: 1., y: 1., z: 0.},
+                                  &Vector{x: 1., y: 1., z: 2.},
+                                  &Material::default());
+        let ray = Ray::new(&Vector{x: 5., y: -3., z: 1.}, &Vector{x: -1., y: 1., z: 0.});
+        let collision_point = rect.collision_point(&ray).unwrap();
+        assert_approx_eq!(collision_point.x, 1.);
+        assert_approx_eq!(collision_point.y, 1.);
+        assert_approx_eq!(collision_point.z, 1.);
+        // Just outside of the first edge
+        let ray = Ray::new(&Vector{x: 5., y: -3., z: -0.2}, &Vector{x: -1., y: 1., z: 0.});
+        assert!(rect.collision_point(&ray).is_none());
+
+        let normal = rect.normal_at_point(&collision_point).unwrap();
+        assert_approx_eq!(normal.x, std::f64::consts::FRAC_1_SQRT_2);
+        assert_approx_eq!(normal.y, -std::f64::consts::FRAC_1_SQRT_2);
+        assert_approx_eq!(normal.z, 0.);
+
+        let bounding_box = rect.bounding_box().unwrap();
+        assert_approx_eq!(bounding_box.max.x, 2.);
+        assert_approx_eq!(bounding_box.max.z, 2.);
+    }
+}
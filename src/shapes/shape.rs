@@ -1,6 +1,18 @@
 use crate::ray::Ray;
 use crate::vector::Vector;
 use crate::pixel::Color;
+use crate::aabb::Aabb;
+
+// Where the ray hits the shape and how the surface looks there
+#[derive(Clone, Copy, Debug)]
+pub struct Hit
+{
+    pub point: Vector,
+    // Normal of the surface, pointing outside of the shape
+    pub normal: Vector,
+    // Texture coordinates, if the shape has them
+    pub uv: Option<(f64, f64)>
+}
 
 pub trait Collision
 {
@@ -10,10 +22,25 @@ pub trait Collision
     fn collision_point(&self, ray: &Ray) -> Option<Vector>;
     //Returns (if possible) the normal vector of the shape for a given point on the shape
     fn normal_at_point(&self, point: &Vector) -> Option<Vector>;
+    // Returns (if possible) the first hit of the ray with the normal at it. Shapes that know more
+    // about the hit than its point (i.e. which triangle of the mesh it is) don't need to look it up again.
+    fn collision(&self, ray: &Ray) -> Option<Hit>
+    {
+        let point = self.collision_point(ray)?;
+        Some(Hit{
+            point,
+            normal: self.normal_at_point(&point)?,
+            uv: None
+        })
+    }
     // Returns the "up" direction of shape
+    #[allow(dead_code)]
     fn up_direction(&self) -> Vector;
     // Returns the center of the shape
+    #[allow(dead_code)]
     fn position(&self) -> Vector;
+    // Returns the box that contains the whole shape, or None if the shape is unbounded
+    fn bounding_box(&self) -> Option<Aabb>;
 }
 
 pub trait MaterialTrait
@@ -21,7 +48,12 @@ pub trait MaterialTrait
     fn reflectivity(&self) -> f64;
     fn color(&self) -> Color;
     fn is_specular(&self) -> bool;
+    // Refractive index of transparent shapes, None if the shape is opaque
+    fn refractive_index(&self) -> Option<f64>;
+    // Radiance sent out by the surface itself
+    fn emission(&self) -> Color;
 }
 
-pub trait Shape: Collision + MaterialTrait
+// Shapes are shared between the render threads, so they have to be Send + Sync
+pub trait Shape: Collision + MaterialTrait + Send + Sync
 {}
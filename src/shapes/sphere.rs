@@ -4,6 +4,10 @@ use crate::vector::Vector;
 use crate::shapes::{Collision, MaterialTrait, Shape};
 use crate::material::Material;
 use crate::pixel::Color;
+use crate::aabb::Aabb;
+
+// Smallest parameter of the ray that counts as a hit
+const MIN_RAY_PARAMETER: f64 = 0.00001;
 
 pub struct Sphere
 {
@@ -16,7 +20,7 @@ impl Collision for Sphere
 {
     fn can_collide(&self, ray: &Ray) -> bool
     {
-        return ray.distance_to_point(self.position) <= self.radius;
+        ray.distance_to_point(self.position) <= self.radius
     }
     fn collision_point(&self, ray: &Ray) -> Option<Vector>
     {
@@ -34,14 +38,21 @@ impl Collision for Sphere
         }
         // t is a factor which describes the point of interception
         // point of collision = start of the ray + t * direction of the ray
-        let t = (-b - delta.sqrt()) / (2. * a);
-        if t < -0.000000000001  //To avoid f64 precision error, because it could still collide and be very small negative number
+        let near = (-b - delta.sqrt()) / (2. * a);
+        let far = (-b + delta.sqrt()) / (2. * a);
+        // Rays that start inside of the sphere (i.e. refracted ones) hit its far side. Points very
+        // close to the start are skipped, so rays leaving the surface don't hit it again.
+        if near > MIN_RAY_PARAMETER
         {
-            None
+            Some(ray.start_position + ray.direction * near)
+        }
+        else if far > MIN_RAY_PARAMETER
+        {
+            Some(ray.start_position + ray.direction * far)
         }
         else
         {
-            Some(ray.start_position + ray.direction * t)
+            None
         }
     }
     fn normal_at_point(&self, point: &Vector) -> Option<Vector>
@@ -69,7 +80,16 @@ impl Collision for Sphere
     }
     fn position(&self) -> Vector
     {
-        return self.position;
+        self.position
+    }
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        let half_size = Vector{
+            x: self.radius,
+            y: self.radius,
+            z: self.radius
+        };
+        Some(Aabb::new(&(self.position - half_size), &(self.position + half_size)))
     }
 }
 
@@ -86,6 +106,14 @@ impl MaterialTrait for Sphere
     fn is_specular(&self) -> bool {
         self.material.is_specular
     }
+
+    fn refractive_index(&self) -> Option<f64> {
+        self.material.refractive_index
+    }
+
+    fn emission(&self) -> Color {
+        self.material.emission
+    }
 }
 impl Shape for Sphere
 {
@@ -128,9 +156,9 @@ mod test
             material: Material::default()
         };
 
-        assert_eq!(true, sphere_just_big_enough.can_collide(&ray));
-        assert_eq!(true, sphere_huge.can_collide(&ray));
-        assert_eq!(false, sphere_small.can_collide(&ray));
+        assert!(sphere_just_big_enough.can_collide(&ray));
+        assert!(sphere_huge.can_collide(&ray));
+        assert!(!sphere_small.can_collide(&ray));
     }
 
     #[test]
@@ -157,8 +185,8 @@ mod test
             },
             material: Material::default()
         };
-        assert_eq!(true, sphere_on_border.can_collide(&ray));
-        assert_eq!(true, sphere_inside.can_collide(&ray));
+        assert!(sphere_on_border.can_collide(&ray));
+        assert!(sphere_inside.can_collide(&ray));
     }
 
     #[test]
@@ -187,6 +215,35 @@ mod test
         };
     }
 
+    #[test]
+    fn collision_point_from_inside()
+    {
+        let sphere = Sphere{
+            radius: 2.,
+            position: Vector{
+                x: 0.,
+                y: 0.,
+                z: 0.
+            },
+            material: Material::default()
+        };
+        let ray = Ray::new(&Vector::new(), &Vector{x: 0., y: 0., z: -1.});
+        let point = sphere.collision_point(&ray).unwrap();
+        assert_approx_eq!(point.z, -2.);
+
+        // Starting on the surface and going through the sphere
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 2.}, &Vector{x: 0., y: 0., z: -1.});
+        let point = sphere.collision_point(&ray).unwrap();
+        assert_approx_eq!(point.z, -2.);
+
+        // Starting on the surface and going away
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 2.}, &Vector{x: 0., y: 0., z: 1.});
+        assert!(sphere.collision_point(&ray).is_none());
+        // Sphere behind the ray
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 5.}, &Vector{x: 0., y: 0., z: 1.});
+        assert!(sphere.collision_point(&ray).is_none());
+    }
+
     #[test]
     fn normal_at_point()
     {
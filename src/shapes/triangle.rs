@@ -0,0 +1,221 @@
+use crate::ray::Ray;
+use crate::vector::Vector;
+use crate::shapes::{Collision, MaterialTrait, Shape};
+use crate::material::Material;
+use crate::pixel::Color;
+use crate::aabb::Aabb;
+
+// Smallest parameter of the ray that counts as a hit, so rays starting on the surface don't hit it again
+const MIN_RAY_PARAMETER: f64 = 0.00001;
+
+// Möller–Trumbore intersection. Returns the parameter of the ray in which it hits the triangle
+// and the barycentric coordinates (u, v) of the hit, so that point = (1 - u - v) * a + u * b + v * c.
+// Points on the edges count as hits, so there are no gaps between triangles that share an edge.
+pub fn intersect_triangle(a: &Vector, b: &Vector, c: &Vector, ray: &Ray) -> Option<(f64, f64, f64)>
+{
+    let first_edge = *b - *a;
+    let second_edge = *c - *a;
+    let p = ray.direction * second_edge;
+    let determinant = first_edge.dot(p);
+    // Ray is parallel to the triangle (or the triangle is degenerated)
+    if determinant.abs() < 1e-12 * first_edge.distance() * second_edge.distance() * ray.direction.distance()
+    {
+        return None;
+    }
+    let inverse_determinant = 1. / determinant;
+    let start_to_a = ray.start_position - *a;
+    let u = start_to_a.dot(p) * inverse_determinant;
+    if !(0. ..=1.).contains(&u)
+    {
+        return None;
+    }
+    let q = start_to_a * first_edge;
+    let v = ray.direction.dot(q) * inverse_determinant;
+    if v < 0. || u + v > 1.
+    {
+        return None;
+    }
+    let parameter = second_edge.dot(q) * inverse_determinant;
+    if parameter > MIN_RAY_PARAMETER
+    {
+        return Some((parameter, u, v));
+    }
+    None
+}
+
+// Barycentric coordinates (u, v) of the projection of the point on the plane of the triangle
+// and the distance of the point from that plane
+pub fn barycentric_coordinates(a: &Vector, b: &Vector, c: &Vector, point: &Vector) -> (f64, f64, f64)
+{
+    let first_edge = *b - *a;
+    let second_edge = *c - *a;
+    let normal = first_edge * second_edge;
+    let normal_length_squared = normal.dot(normal);
+    let a_to_point = *point - *a;
+    let u = (a_to_point * second_edge).dot(normal) / normal_length_squared;
+    let v = (first_edge * a_to_point).dot(normal) / normal_length_squared;
+    let distance = a_to_point.dot(normal).abs() / normal_length_squared.sqrt();
+    (u, v, distance)
+}
+
+pub struct Triangle
+{
+    pub vertices: [Vector; 3],
+    pub material: Material
+}
+
+impl Triangle
+{
+    pub fn new(a: &Vector, b: &Vector, c: &Vector, material: &Material) -> Triangle
+    {
+        Triangle{
+            vertices: [*a, *b, *c],
+            material: *material
+        }
+    }
+
+    // Normal on the side from which the vertices are ordered counterclockwise
+    fn normal(&self) -> Vector
+    {
+        ((self.vertices[1] - self.vertices[0]) * (self.vertices[2] - self.vertices[0])).normalized()
+    }
+}
+
+impl MaterialTrait for Triangle
+{
+    fn reflectivity(&self) -> f64 {
+        self.material.reflectivity
+    }
+
+    fn color(&self) -> Color {
+        self.material.color
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular
+    }
+
+    fn refractive_index(&self) -> Option<f64> {
+        self.material.refractive_index
+    }
+
+    fn emission(&self) -> Color {
+        self.material.emission
+    }
+}
+
+impl Collision for Triangle
+{
+    fn can_collide(&self, ray: &Ray) -> bool
+    {
+        // Only rays parallel to the triangle can be rejected that cheaply
+        self.normal().dot(ray.direction) != 0.
+    }
+    fn collision_point(&self, ray: &Ray) -> Option<Vector>
+    {
+        let [a, b, c] = &self.vertices;
+        let (parameter, _, _) = intersect_triangle(a, b, c, ray)?;
+        Some(ray.start_position + ray.direction * parameter)
+    }
+    fn normal_at_point(&self, _point: &Vector) -> Option<Vector>
+    {
+        Some(self.normal())
+    }
+    fn up_direction(&self) -> Vector
+    {
+        self.normal()
+    }
+    fn position(&self) -> Vector
+    {
+        (self.vertices[0] + self.vertices[1] + self.vertices[2]) * (1. / 3.)
+    }
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        let bounding_box = Aabb::new(&self.vertices[0], &self.vertices[0])
+            .grow(&self.vertices[1])
+            .grow(&self.vertices[2]);
+        // Axis aligned triangles have flat boxes, give them some thickness
+        let padding = Vector{x: 1e-9, y: 1e-9, z: 1e-9};
+        Some(Aabb::new(&(bounding_box.min - padding), &(bounding_box.max + padding)))
+    }
+}
+
+impl Shape for Triangle
+{}
+
+#[cfg(test)]
+mod test
+{
+    use crate::vector::Vector;
+    use crate::shapes::{Triangle, Collision};
+    use crate::shapes::triangle::barycentric_coordinates;
+    use crate::ray::Ray;
+    use crate::material::Material;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn get_triangle() -> Triangle
+    {
+        Triangle::new(&Vector{x: 0., y: 0., z: 0.},
+                      &Vector{x: 2., y: 0., z: 0.},
+                      &Vector{x: 0., y: 2., z: 0.},
+                      &Material::default())
+    }
+
+    #[test]
+    fn collision_point()
+    {
+        let triangle = get_triangle();
+        let ray = Ray::new(&Vector{x: 0.5, y: 0.5, z: 5.}, &Vector{x: 0., y: 0., z: -2.});
+        let collision_point = triangle.collision_point(&ray).unwrap();
+        assert_approx_eq!(collision_point.x, 0.5);
+        assert_approx_eq!(collision_point.y, 0.5);
+        assert_approx_eq!(collision_point.z, 0.);
+
+        // From behind
+        let ray = Ray::new(&Vector{x: 0.5, y: 0.5, z: -5.}, &Vector{x: 0., y: 0., z: 1.});
+        assert!(triangle.collision_point(&ray).is_some());
+    }
+
+    #[test]
+    fn miss()
+    {
+        let triangle = get_triangle();
+        // Outside of the hypotenuse
+        let ray = Ray::new(&Vector{x: 1.1, y: 1.1, z: 5.}, &Vector{x: 0., y: 0., z: -1.});
+        assert!(triangle.collision_point(&ray).is_none());
+        // Pointing away
+        let ray = Ray::new(&Vector{x: 0.5, y: 0.5, z: 5.}, &Vector{x: 0., y: 0., z: 1.});
+        assert!(triangle.collision_point(&ray).is_none());
+        // Parallel
+        let ray = Ray::new(&Vector{x: -1., y: 0.5, z: 0.}, &Vector{x: 1., y: 0., z: 0.});
+        assert!(!triangle.can_collide(&ray));
+        assert!(triangle.collision_point(&ray).is_none());
+    }
+
+    #[test]
+    fn shared_edge_has_no_gap()
+    {
+        let first = get_triangle();
+        let second = Triangle::new(&Vector{x: 2., y: 0., z: 0.},
+                                   &Vector{x: 2., y: 2., z: 0.},
+                                   &Vector{x: 0., y: 2., z: 0.},
+                                   &Material::default());
+        // Exactly on the shared edge
+        let ray = Ray::new(&Vector{x: 1., y: 1., z: 5.}, &Vector{x: 0., y: 0., z: -1.});
+        assert!(first.collision_point(&ray).is_some() || second.collision_point(&ray).is_some());
+    }
+
+    #[test]
+    fn normal_and_barycentric_coordinates()
+    {
+        let triangle = get_triangle();
+        let normal = triangle.normal_at_point(&Vector::new()).unwrap();
+        assert_approx_eq!(normal.z, 1.);
+
+        let [a, b, c] = &triangle.vertices;
+        let (u, v, distance) = barycentric_coordinates(a, b, c, &Vector{x: 0.5, y: 1., z: 0.25});
+        assert_approx_eq!(u, 0.25);
+        assert_approx_eq!(v, 0.5);
+        assert_approx_eq!(distance, 0.25);
+    }
+}
@@ -0,0 +1,210 @@
+// Conversion of the rendered radiance to displayable 8 bit colors: exposure, tone mapping
+// of the high values to 0..1 range and sRGB encoding.
+use crate::pixel::Color;
+use std::fmt;
+
+// Operators that compress the radiance to 0..1 range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapping
+{
+    // Everything above 1 is white
+    Clamp,
+    // L / (1 + L), never reaches white
+    Reinhard,
+    // Reinhard that maps the luminance of the white point (and everything above it) to white
+    ExtendedReinhard{white_point: f64},
+    // Filmic curve of the Academy Color Encoding System (Narkowicz's fit)
+    Aces,
+}
+
+impl ToneMapping
+{
+    // Names used in the scene files and on the command line, the white point of the extended
+    // Reinhard operator is set separately
+    pub fn from_name(name: &str) -> Option<ToneMapping>
+    {
+        match name
+        {
+            "clamp" => Some(ToneMapping::Clamp),
+            "reinhard" => Some(ToneMapping::Reinhard),
+            "extended-reinhard" => Some(ToneMapping::ExtendedReinhard{white_point: 4.}),
+            "aces" => Some(ToneMapping::Aces),
+            _ => None
+        }
+    }
+
+    pub fn apply(self, color: Color) -> Color
+    {
+        match self
+        {
+            ToneMapping::Clamp => Color{
+                r: color.r.clamp(0., 1.),
+                g: color.g.clamp(0., 1.),
+                b: color.b.clamp(0., 1.)
+            },
+            // Both Reinhard operators scale the luminance, so the hue doesn't change
+            ToneMapping::Reinhard => scale_luminance(color, |luminance| luminance / (1. + luminance)),
+            ToneMapping::ExtendedReinhard{white_point} => scale_luminance(color, |luminance| {
+                luminance * (1. + luminance / (white_point * white_point)) / (1. + luminance)
+            }),
+            ToneMapping::Aces => {
+                let curve = |x: f64| {
+                    let x = x.max(0.);
+                    ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.)
+                };
+                Color{
+                    r: curve(color.r),
+                    g: curve(color.g),
+                    b: curve(color.b)
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ToneMapping
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            ToneMapping::Clamp => write!(f, "clamp"),
+            ToneMapping::Reinhard => write!(f, "reinhard"),
+            ToneMapping::ExtendedReinhard{white_point} => write!(f, "extended-reinhard (white point {})", white_point),
+            ToneMapping::Aces => write!(f, "aces"),
+        }
+    }
+}
+
+// Relative luminance of the linear color (Rec. 709 primaries)
+pub fn luminance(color: Color) -> f64
+{
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+fn scale_luminance<F: Fn(f64) -> f64>(color: Color, curve: F) -> Color
+{
+    let luminance = luminance(color);
+    if luminance <= 0.
+    {
+        return Color::new();
+    }
+    color * (curve(luminance) / luminance)
+}
+
+// sRGB transfer function of the channel in 0..1 range
+pub fn linear_to_srgb(channel: f64) -> f64
+{
+    if channel <= 0.0031308
+    {
+        return channel * 12.92;
+    }
+    1.055 * channel.powf(1. / 2.4) - 0.055
+}
+
+pub fn srgb_to_linear(channel: f64) -> f64
+{
+    if channel <= 0.04045
+    {
+        return channel / 12.92;
+    }
+    ((channel + 0.055) / 1.055).powf(2.4)
+}
+
+// Whole output transform: radiance * 2^exposure -> tone mapping -> sRGB -> 8 bits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapper
+{
+    // In stops, +1 doubles the brightness
+    pub exposure: f64,
+    pub operator: ToneMapping,
+}
+
+impl ToneMapper
+{
+    pub fn new(exposure: f64, operator: ToneMapping) -> ToneMapper
+    {
+        ToneMapper{
+            exposure,
+            operator
+        }
+    }
+
+    pub fn to_rgb8(&self, color: Color) -> [u8; 3]
+    {
+        let mapped = self.operator.apply(color * 2f64.powf(self.exposure));
+        Color{
+            r: linear_to_srgb(mapped.r.clamp(0., 1.)),
+            g: linear_to_srgb(mapped.g.clamp(0., 1.)),
+            b: linear_to_srgb(mapped.b.clamp(0., 1.))
+        }.to_rgb8()
+    }
+
+    pub fn to_u32(&self, color: Color) -> u32
+    {
+        let [r, g, b] = self.to_rgb8(color);
+        (r as u32) << 16 | (g as u32) << 8 | b as u32
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::tonemap::{ToneMapping, ToneMapper, linear_to_srgb, srgb_to_linear, luminance};
+    use crate::pixel::Color;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn srgb_round_trip()
+    {
+        assert_approx_eq!(linear_to_srgb(0.), 0.);
+        assert_approx_eq!(linear_to_srgb(1.), 1.);
+        // Middle grey is encoded brighter
+        assert_approx_eq!(linear_to_srgb(0.18), 0.4614, 1e-4);
+        for value in [0.001, 0.01, 0.2, 0.5, 0.9].iter()
+        {
+            assert_approx_eq!(srgb_to_linear(linear_to_srgb(*value)), *value);
+        }
+    }
+
+    #[test]
+    fn operators()
+    {
+        let bright = Color{r: 8., g: 4., b: 2.};
+        let clamped = ToneMapping::Clamp.apply(bright);
+        assert_approx_eq!(clamped.r, 1.);
+        assert_approx_eq!(clamped.b, 1.);
+
+        // Reinhard keeps the ratio of the channels
+        let reinhard = ToneMapping::Reinhard.apply(bright);
+        assert_approx_eq!(reinhard.r / reinhard.g, 2.);
+        let grey = ToneMapping::Reinhard.apply(Color::white());
+        assert_approx_eq!(luminance(grey), 0.5);
+
+        // Extended Reinhard reaches white at the white point
+        let white = ToneMapping::ExtendedReinhard{white_point: 4.}.apply(Color::white() * 4.);
+        assert_approx_eq!(luminance(white), 1.);
+
+        let aces = ToneMapping::Aces.apply(bright);
+        assert!(aces.r <= 1. && aces.r > aces.g && aces.g > aces.b);
+        assert_approx_eq!(ToneMapping::Aces.apply(Color::new()).r, 0.);
+        assert!(ToneMapping::Aces.apply(Color::white() * 100.).r > 0.99);
+
+        assert_eq!(ToneMapping::from_name("aces"), Some(ToneMapping::Aces));
+        assert_eq!(ToneMapping::from_name("filmic"), None);
+    }
+
+    #[test]
+    fn exposure()
+    {
+        let mapper = ToneMapper::new(0., ToneMapping::Clamp);
+        assert_eq!(mapper.to_rgb8(Color::white()), [255, 255, 255]);
+        assert_eq!(mapper.to_rgb8(Color::white() * 2.), [255, 255, 255]);
+        assert_eq!(mapper.to_u32(Color{r: 1., g: 0., b: 0.}), 0xFF0000);
+
+        // One stop down halves the radiance
+        let darker = ToneMapper::new(-1., ToneMapping::Clamp);
+        let [r, _, _] = darker.to_rgb8(Color::white());
+        assert_eq!(r, (linear_to_srgb(0.5) * 255.).round() as u8);
+    }
+}
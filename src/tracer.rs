@@ -0,0 +1,591 @@
+use crate::{
+    world::World,
+    pixel::{Pixel, Color},
+    ray::Ray,
+    vector::Vector,
+    raytree::*,
+    shapes::{Shape, Hit},
+    settings::RenderSettings,
+    material::fresnel_reflectance,
+    view::{View, Lens, PrimaryRays},
+    filter,
+    sampler::{self, Sampler, RandomSampler},
+};
+use std::f64::consts;
+
+// Rendered chunk of the image, sent back from the worker to the camera
+pub struct Tile{
+    pub chunk_num: u32,
+    // Chunk's pixels, row by row
+    pub pixels: Vec<Pixel>,
+    pub num_of_rays: u64
+}
+
+// Renders whole chunks of the image. Every worker thread owns its own tracer
+// (and so its own ray arena and sampler), only the world is shared.
+pub struct Tracer<'a>{
+    world: &'a World,
+    settings: &'a RenderSettings,
+    primary_rays: PrimaryRays,
+    // Samples of the pixels, the lens and the scattered rays, seeded again for every pixel so the image
+    // doesn't depend on the number of threads or the size of the chunks
+    sampler: RandomSampler,
+    arena: RayArena
+}
+
+impl<'a> Tracer<'a>{
+    pub fn new(world: &'a World, settings: &'a RenderSettings, view: &View, lens: &Lens) -> Tracer<'a>
+    {
+        Tracer{
+            world,
+            settings,
+            primary_rays: PrimaryRays::new(view, lens, settings),
+            sampler: RandomSampler::new(settings.seed),
+            arena: RayArena::new(settings.max_ray_depth)
+        }
+    }
+
+    pub fn render_chunk(&mut self, chunk_num: u32) -> Tile
+    {
+        let settings = self.settings;
+        //Clear the arena
+        self.arena.nodes.clear();
+
+        let chunk_x = chunk_num % settings.chunks_per_row();
+        let start_x = settings.width_chunk * chunk_x;
+        let end_x = settings.width_chunk * (chunk_x + 1);
+        let chunk_y = chunk_num / settings.chunks_per_row();
+        let start_y = settings.height_chunk * chunk_y;
+        let end_y = settings.height_chunk * (chunk_y + 1);
+
+        let mut tile = Tile{
+            chunk_num,
+            pixels: Vec::with_capacity((settings.width_chunk * settings.height_chunk) as usize),
+            num_of_rays: 0
+        };
+        for y in start_y..end_y
+        {
+            for x in start_x..end_x
+            {
+                let pixel = self.render_pixel(x, y, &mut tile.num_of_rays);
+                tile.pixels.push(pixel);
+            }
+        }
+        tile
+    }
+
+    // Combines the samples of the pixel with the pixel filter. The color, albedo and normal are
+    // filtered, the depth and the ids can't be mixed, so they come from the sample closest to the center.
+    fn render_pixel(&mut self, x: u32, y: u32, num_of_rays: &mut u64) -> Pixel
+    {
+        let settings = self.settings;
+        let filter = settings.pixel_filter;
+        self.sampler = RandomSampler::for_pixel(settings.seed, y as u64 * settings.width as u64 + x as u64);
+        let samples = if settings.samples_per_pixel == 1
+        {
+            vec![(0.5, 0.5)]
+        }
+        else
+        {
+            filter::stratified_samples(settings.samples_per_pixel, &mut self.sampler)
+        };
+        let mut pixel = Pixel::new();
+        let mut weighted = (Color::new(), Color::new(), Vector::new());
+        let mut total_weight = 0.;
+        let mut plain = (Color::new(), Color::new(), Vector::new());
+        let mut smallest_offset = f64::MAX;
+        for (sample_x, sample_y) in samples.iter()
+        {
+            // From the unit square to the whole area of the filter
+            let offset_x = (2. * sample_x - 1.) * filter.radius();
+            let offset_y = (2. * sample_y - 1.) * filter.radius();
+            let lens_sample = self.sampler.next_2d();
+            // Outside of the projection (i.e. corners of the fisheye image) is black
+            let sample = match self.primary_rays.ray(x as f64 + 0.5 + offset_x, y as f64 + 0.5 + offset_y, lens_sample)
+            {
+                Some(ray) => self.render_sample(&ray, num_of_rays),
+                None => Pixel::new()
+            };
+            let weight = filter.weight(offset_x, offset_y);
+            weighted.0 += sample.color * weight;
+            weighted.1 += sample.albedo * weight;
+            weighted.2 = weighted.2 + sample.normal * weight;
+            total_weight += weight;
+            plain.0 += sample.color;
+            plain.1 += sample.albedo;
+            plain.2 = plain.2 + sample.normal;
+
+            let offset = offset_x.hypot(offset_y);
+            if offset < smallest_offset
+            {
+                smallest_offset = offset;
+                pixel.depth = sample.depth;
+                pixel.object_id = sample.object_id;
+                pixel.material_id = sample.material_id;
+            }
+        }
+        // Negative lobes of the Mitchell filter can cancel out the weights of a few samples
+        let (sums, total_weight) = if total_weight > 1e-9 {(weighted, total_weight)} else {(plain, samples.len() as f64)};
+        pixel.color = sums.0 * (1. / total_weight);
+        pixel.albedo = sums.1 * (1. / total_weight);
+        if sums.2.distance() > 0.
+        {
+            pixel.normal = sums.2.normalized();
+        }
+        pixel
+    }
+
+    // Color and the first hit of the primary ray
+    fn render_sample(&mut self, ray: &Ray, num_of_rays: &mut u64) -> Pixel
+    {
+        let world = self.world;
+        let mut pixel = Pixel::new();
+        // Lights are only seen in the color, the other passes treat them like the sky
+        if let Some(radiance) = Tracer::light_hit(world, ray)
+        {
+            pixel.color = radiance;
+            return pixel;
+        }
+        if let Some((idx, distance, hit)) = world.closest_collision(ray)
+        {
+            let shape = world.shape(idx);
+            pixel.depth = distance;
+            pixel.normal = Tracer::facing_normal(&hit, &ray.direction);
+            pixel.albedo = shape.color();
+            pixel.object_id = idx as u32 + 1;
+            pixel.material_id = world.material_id(idx);
+
+            // Create reflected rays and add them to the arena
+            let node_id = self.arena.add_node(NodeId::Root, ray);
+            self.shoot_reflected_rays(world, node_id);
+            pixel.color = self.calculate_node_color(world, node_id);
+            // Remove the rays to save space
+            *num_of_rays += self.arena.nodes.len() as u64;
+            self.arena.remove_node_with_childs(node_id);
+        }
+        else
+        {
+            pixel.color = Color::from_srgb8(128, 218, 235);
+        }
+        pixel
+    }
+
+    // Normal of the shape at the hit, turned towards the side that the ray comes from,
+    // so that one sided shapes (i.e. rectangles) look the same from both sides
+    fn facing_normal(hit: &Hit, ray_direction: &Vector) -> Vector{
+        let normal = hit.normal;
+        if normal.dot(*ray_direction) > 0.{
+            return normal * -1.;
+        }
+        normal
+    }
+
+    // Radiance of the area light that the ray hits before any shape
+    fn light_hit(world: &World, ray: &Ray) -> Option<Color>{
+        let (idx, distance) = world.closest_light(ray)?;
+        if world.occluded(ray, distance){
+            return None;
+        }
+        Some(world.lights[idx].radiance(&ray.direction))
+    }
+
+    fn shoot_reflected_rays(&mut self, world: &World, id: NodeId){
+        let ray_node_opt = self.arena.get_node(id);
+        //If parent exists
+        if let Some(ray_node) = ray_node_opt{
+            // Rays end at the lights
+            if ray_node.recursion_depth >= self.settings.max_ray_depth || Tracer::light_hit(world, &ray_node.ray).is_some(){
+                return;
+            }
+            //If the collision occurred
+            if let Some((collision_shape, hit)) = world.item_that_collide(&ray_node.ray){
+                let new_collision_point = hit.point;
+                if collision_shape.refractive_index().is_some(){
+                    let direction = ray_node.ray.direction;
+                    self.shoot_dielectric_rays(world, id, collision_shape.as_ref(), &hit, &direction);
+                }
+                else if collision_shape.is_specular(){
+                    let normal = Tracer::facing_normal(&hit, &ray_node.ray.direction);
+                    let new_direction = ray_node.ray.direction.reflection(normal);
+                    let new_ray = Ray::new(&new_collision_point, &new_direction);
+                    let new_node_id = self.arena.add_node(id, &new_ray);
+                    self.shoot_reflected_rays(world, new_node_id);
+                }
+                else {
+                    // Calculate the number of required rays
+                    let mut num_of_rays = self.settings.num_of_reflected_rays as f64;
+                    if ray_node.recursion_depth > 0 {
+                        let denominator = (ray_node.recursion_depth + 1) as f64 * self.settings.scattered_rays_falloff;
+                        num_of_rays /= denominator;
+                    }
+                    let num_of_rays = (num_of_rays as usize + 1).min(self.settings.num_of_reflected_rays);
+                    let normal = Tracer::facing_normal(&hit, &ray_node.ray.direction).normalized();
+                    for _ in 0..num_of_rays {
+                        // Fresh direction for every ray, the diffuse surface scatters the light around the normal
+                        let new_direction = sampler::cosine_hemisphere(&normal, self.sampler.next_2d());
+                        let new_ray = Ray::new(&new_collision_point, &new_direction);
+                        let new_node_id = self.arena.add_node(id, &new_ray);
+                        // Lights are already sampled at every diffuse hit, hitting them again would count them twice
+                        if let Some(node) = self.arena.get_mut_node(new_node_id){
+                            node.counts_emission = false;
+                        }
+                        self.shoot_reflected_rays(world, new_node_id);
+                    }
+                }
+            }
+        }
+    }
+
+    // Splits the ray that hits a transparent shape into the reflected and the refracted part,
+    // weighted by the Fresnel reflectance. Above the critical angle the whole light is reflected.
+    fn shoot_dielectric_rays(&mut self, world: &World, id: NodeId, shape: &dyn Shape, hit: &Hit, direction: &Vector){
+        let refractive_index = shape.refractive_index().unwrap();
+        let direction = direction.normalized();
+        let point = &hit.point;
+        let normal = Tracer::facing_normal(hit, &direction);
+        // Normals of the shapes point outside, so the ray that goes against it enters the shape
+        let entering = hit.normal.dot(direction) < 0.;
+        let ratio = if entering {1. / refractive_index} else {refractive_index};
+        let reflected_ray = Ray::new(point, &direction.reflection(normal));
+        match direction.refraction(normal, ratio){
+            Some(refracted_direction) => {
+                // The approximation uses the angle on the side of the air
+                let cosine = if entering {-direction.dot(normal)} else {-refracted_direction.dot(normal)};
+                let reflectance = fresnel_reflectance(refractive_index, cosine);
+                let reflected_id = self.arena.add_weighted_node(id, &reflected_ray, reflectance);
+                self.shoot_reflected_rays(world, reflected_id);
+                let refracted_id = self.arena.add_weighted_node(id, &Ray::new(point, &refracted_direction), 1. - reflectance);
+                self.shoot_reflected_rays(world, refracted_id);
+            },
+            None => {
+                let reflected_id = self.arena.add_node(id, &reflected_ray);
+                self.shoot_reflected_rays(world, reflected_id);
+            }
+        }
+    }
+
+    // Light coming through a transparent shape: the weighted sum of the reflected and the refracted light,
+    // the latter tinted by the color of the shape
+    fn calculate_dielectric_color(&mut self, world: &World, ray: &Ray, children: &[u32], shape: &dyn Shape, hit: &Hit) -> Color{
+        let normal = hit.normal;
+        let incoming_side = ray.direction.dot(normal) > 0.;
+        let mut result = shape.emission();
+        for child in children.iter(){
+            let (weight, direction) = match self.arena.get_node(NodeId::Parent(*child)){
+                Some(child_node) => (child_node.weight, child_node.ray.direction),
+                None => continue
+            };
+            let color = self.calculate_node_color(world, NodeId::Parent(*child)) * weight;
+            // Refracted rays continue on the same side as the incoming ray
+            if (direction.dot(normal) > 0.) == incoming_side{
+                result += color * shape.color();
+            }
+            else{
+                result += color;
+            }
+        }
+        result
+    }
+
+    fn calculate_node_color(&mut self, world: &World, id: NodeId) -> Color{
+        if let NodeId::Parent(_) = id{
+            // Light sampling needs the sampler, so the node is copied out of the arena
+            let (ray, children, counts_emission) = match self.arena.get_node(id){
+                Some(node) => (node.ray, node.child.clone(), node.counts_emission),
+                None => return Color::white()
+            };
+            if let Some(radiance) = Tracer::light_hit(world, &ray){
+                return if counts_emission {radiance} else {Color::new()};
+            }
+            // If it is the last ray, calculate the light that is reaching this point
+            if children.is_empty(){
+                return self.calculate_last_node_color(world, &ray);
+            }
+            // The surface passes on only its albedo of the light that it gets from the other shapes
+            let albedo = match world.item_that_collide(&ray){
+                Some((item, hit)) => {
+                    if item.refractive_index().is_some(){
+                        return self.calculate_dielectric_color(world, &ray, &children, item.as_ref(), &hit);
+                    }
+                    item.color() * item.reflectivity()
+                },
+                None => Color::white()
+            };
+            let one_over_num_of_rays = 1. / children.len() as f64;
+            let mut bounced = Color::new();
+            // Diffuse rays are already spread with the cosine to the normal, so every one of them
+            // counts the same and the bounced light is their plain mean. The falloff only makes
+            // fewer of them.
+            for child in children.iter(){
+                let weight = match self.arena.get_node(NodeId::Parent(*child)){
+                    Some(child_node) => child_node.weight,
+                    None => continue
+                };
+                bounced += self.calculate_node_color(world, NodeId::Parent(*child)) * weight * one_over_num_of_rays;
+            }
+            return self.calculate_last_node_color(world, &ray) + bounced * albedo;
+        }
+        Color::white()
+    }
+
+    // Light leaving the hit of the ray: the light that the shape sends out by itself and the light coming
+    // straight from the lights (one random point of every light). Glowing shapes are not sampled like the lights,
+    // so every ray that hits them counts their light, the diffuse ones too.
+    fn calculate_last_node_color(&mut self, world: &World, ray: &Ray) -> Color{
+        if let Some((item, hit)) = world.item_that_collide(ray){
+            // Transparent shapes have no diffuse surface to light up
+            if item.refractive_index().is_some(){
+                return item.emission();
+            }
+            let collision_point = hit.point;
+            let normal = Tracer::facing_normal(&hit, &ray.direction).normalized();
+
+            let mut resulting_color = item.emission();
+            let shadow_origin = Ray::offset_origin(&collision_point, &normal);
+            for light in world.lights.iter(){
+                let light_sample = match light.sample(&collision_point, self.sampler.next_2d()){
+                    Some(light_sample) => light_sample,
+                    None => continue
+                };
+                let angle = light_sample.direction.dot(normal);
+                // Only the shapes between the point and the light cast the shadow
+                if angle > 0. && !world.occluded(&Ray::new(&shadow_origin, &light_sample.direction), light_sample.distance){
+                    // Diffuse surface reflects albedo / pi of the irradiance towards every direction
+                    resulting_color += light_sample.irradiance * angle * item.color() * (item.reflectivity() * consts::FRAC_1_PI);
+                }
+            }
+            return resulting_color;
+        }
+        Color::new()
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::tracer::Tracer;
+    use crate::world::World;
+    use crate::settings::RenderSettings;
+    use crate::view::{View, Lens};
+    use crate::shapes::{Sphere, Rectangle};
+    use crate::material::Material;
+    use crate::lightsource::{Lightsource, LightShape};
+    use crate::pixel::Color;
+    use crate::ray::Ray;
+    use crate::vector::Vector;
+    use std::f64::consts;
+    use std::sync::Arc;
+    use assert_approx_eq::assert_approx_eq;
+
+    // Only the light coming straight from the lights, without any bounces
+    fn direct_light(world: &World, ray: &Ray) -> Color
+    {
+        let mut settings = RenderSettings::new();
+        settings.max_ray_depth = 0;
+        let view = View::look_at(&Vector{x: 0., y: 0., z: 5.}, &Vector::new(), &View::default_up()).unwrap();
+        let mut tracer = Tracer::new(world, &settings, &view, &Lens::pinhole());
+        let mut num_of_rays = 0;
+        tracer.render_sample(ray, &mut num_of_rays).color
+    }
+
+    #[test]
+    fn diffuse_shading()
+    {
+        let mut world = World::new();
+        let color = Color{r: 0.8, g: 0.4, b: 0.2};
+        world.add_shape(Arc::new(Sphere{radius: 1.,
+                                       position: Vector{x: 0., y: 0., z: -5.},
+                                       material: Material::new(0.5, color, false)}));
+        world.add_light(Arc::new(Lightsource::new_colored(&Vector{x: 0., y: 0., z: 5.}, 1000., Color{r: 1., g: 1., b: 0.5})));
+
+        // Light 9 units in front of the hit: albedo / pi * power / (4 pi distance^2)
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 5.}, &Vector{x: 0., y: 0., z: -1.});
+        let expected = 0.5 / consts::PI * 1000. / (4. * consts::PI * 81.);
+        let shaded = direct_light(&world, &ray);
+        assert_approx_eq!(shaded.r, 0.8 * expected);
+        assert_approx_eq!(shaded.g, 0.4 * expected);
+        assert_approx_eq!(shaded.b, 0.2 * expected * 0.5);
+
+        // Lights add up
+        world.add_light(Arc::new(Lightsource::new_colored(&Vector{x: 0., y: 0., z: 5.}, 1000., Color{r: 1., g: 1., b: 0.5})));
+        assert_approx_eq!(direct_light(&world, &ray).r, 2. * 0.8 * expected);
+    }
+
+    #[test]
+    fn cosine_of_the_light()
+    {
+        let mut world = World::new();
+        world.add_shape(Arc::new(Sphere{radius: 1.,
+                                       position: Vector{x: 0., y: 0., z: -5.},
+                                       material: Material::new(0.5, Color{r: 0.8, g: 0.4, b: 0.2}, false)}));
+        let hit = Vector{x: 0., y: -(3f64.sqrt()) / 2., z: -4.5};
+        let normal = Vector{x: 0., y: -(3f64.sqrt()) / 2., z: 0.5};
+        // Light in the direction 60 degrees away from the normal, 4 units from the hit, gets half of the light
+        let to_light = Vector{x: 3f64.sqrt() / 2., y: 0., z: 0.} + normal * 0.5;
+        world.add_light(Arc::new(Lightsource::new(&(hit + to_light.normalized() * 4.), 1000.)));
+        let ray = Ray::new(&(hit + Vector{x: 0., y: 0., z: 1.}), &Vector{x: 0., y: 0., z: -1.});
+        let expected = 0.5 / consts::PI * 1000. / (4. * consts::PI * 16.) * 0.5;
+        assert_approx_eq!(direct_light(&world, &ray).r, 0.8 * expected);
+    }
+
+    #[test]
+    fn shadows_end_at_the_light()
+    {
+        let mut world = World::new();
+        world.add_shape(Arc::new(Sphere{radius: 1.,
+                                       position: Vector{x: 0., y: 0., z: -5.},
+                                       material: Material::new(0.5, Color::white(), false)}));
+        world.add_light(Arc::new(Lightsource::new(&Vector{x: 0., y: 0., z: 0.}, 1000.)));
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 5.}, &Vector{x: 0., y: 0., z: -1.});
+        let lit = direct_light(&world, &ray);
+        assert!(lit.r > 0.);
+
+        // Sphere behind the light doesn't shadow the hit, the one between them does
+        world.add_shape(Arc::new(Sphere{radius: 1., position: Vector{x: 0., y: 0., z: 3.}, material: Material::default()}));
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 1.5}, &Vector{x: 0., y: 0., z: -1.});
+        assert_eq!(direct_light(&world, &ray), lit);
+        // Hit at 0 -0.6 -4.2, seen from the side so the camera ray misses the blocker
+        let ray = Ray::new(&Vector{x: 0., y: -2.4, z: -1.8}, &Vector{x: 0., y: 0.6, z: -0.8});
+        assert!(direct_light(&world, &ray).r > 0.);
+        world.add_shape(Arc::new(Sphere{radius: 0.5, position: Vector{x: 0., y: 0., z: -2.}, material: Material::default()}));
+        assert_eq!(direct_light(&world, &ray), Color::new());
+    }
+
+    #[test]
+    fn camera_sees_area_lights()
+    {
+        let mut world = World::new();
+        world.add_light(Arc::new(Lightsource::new_area(&Vector{x: 0., y: -10., z: 0.}, 1000., Color::white(), LightShape::Sphere{radius: 1.})));
+        let ray = Ray::new(&Vector{x: 0., y: -10., z: 5.}, &Vector{x: 0., y: 0., z: -1.});
+        assert_approx_eq!(direct_light(&world, &ray).g, 1000. / (consts::PI * 4. * consts::PI));
+        // Not when a shape is in the way
+        world.add_shape(Arc::new(Sphere{radius: 1., position: Vector{x: 0., y: -10., z: 3.}, material: Material::default()}));
+        assert_eq!(direct_light(&world, &ray), Color::new());
+    }
+
+    #[test]
+    fn soft_shadows()
+    {
+        let mut world = World::new();
+        world.add_shape(Arc::new(Rectangle::new(&Vector{x: -10., y: 0., z: -10.}, &Vector{x: 20., y: 0., z: 0.},
+                                                &Vector{x: 0., y: 0., z: 20.}, &Material::new(1., Color::white(), false))));
+        world.add_light(Arc::new(Lightsource::new_area(&Vector{x: 0., y: -10., z: 0.}, 1000., Color::white(), LightShape::Sphere{radius: 1.})));
+        let mut settings = RenderSettings::new();
+        settings.max_ray_depth = 0;
+        let view = View::look_at(&Vector{x: 0., y: 0., z: 5.}, &Vector::new(), &View::default_up()).unwrap();
+        // Mean of many random points of the light seen from the floor
+        let average_light = |world: &World, x: f64| {
+            let mut tracer = Tracer::new(world, &settings, &view, &Lens::pinhole());
+            let ray = Ray::new(&Vector{x, y: -0.5, z: 0.}, &Vector{x: 0., y: 1., z: 0.});
+            let mut num_of_rays = 0;
+            (0..2000).map(|_| tracer.render_sample(&ray, &mut num_of_rays).color.r).sum::<f64>() / 2000.
+        };
+        let (lit_center, lit_edge) = (average_light(&world, 0.), average_light(&world, 1.5));
+        assert!(lit_center > 0. && lit_edge > 0.);
+
+        // Ball between them hides the whole light from the point below it and a part of it from the point aside
+        world.add_shape(Arc::new(Sphere{radius: 1., position: Vector{x: 0., y: -5., z: 0.}, material: Material::default()}));
+        assert_eq!(average_light(&world, 0.), 0.);
+        let penumbra = average_light(&world, 1.5);
+        assert!(penumbra > 0.05 * lit_edge && penumbra < 0.95 * lit_edge);
+    }
+
+    #[test]
+    fn glowing_shapes()
+    {
+        let mut world = World::new();
+        let mut neon = Material::new(0.5, Color::white(), false);
+        neon.emission = Color{r: 4., g: 1., b: 2.};
+        world.add_shape(Arc::new(Sphere{radius: 1., position: Vector{x: 0., y: -3., z: 0.}, material: neon}));
+        world.add_shape(Arc::new(Rectangle::new(&Vector{x: -10., y: 0., z: -10.}, &Vector{x: 20., y: 0., z: 0.},
+                                                &Vector{x: 0., y: 0., z: 20.}, &Material::new(1., Color::white(), false))));
+        // Seen by the camera just as it glows, without any lights
+        let ray = Ray::new(&Vector{x: 0., y: -3., z: 5.}, &Vector{x: 0., y: 0., z: -1.});
+        assert_eq!(direct_light(&world, &ray), Color{r: 4., g: 1., b: 2.});
+        let floor_ray = Ray::new(&Vector{x: 1., y: -0.5, z: 0.}, &Vector{x: 0., y: 1., z: 0.});
+        assert_eq!(direct_light(&world, &floor_ray), Color::new());
+
+        // The floor only gets its light from the diffuse rays that hit the sphere
+        let mut settings = RenderSettings::new();
+        settings.max_ray_depth = 1;
+        settings.num_of_reflected_rays = 100;
+        let view = View::look_at(&Vector{x: 0., y: 0., z: 5.}, &Vector::new(), &View::default_up()).unwrap();
+        let mut tracer = Tracer::new(&world, &settings, &view, &Lens::pinhole());
+        let mut num_of_rays = 0;
+        let lit_floor = tracer.render_sample(&floor_ray, &mut num_of_rays).color;
+        assert!(lit_floor.r > 0.);
+        assert_approx_eq!(lit_floor.r / lit_floor.g, 4., 1e-9);
+    }
+
+    #[test]
+    fn bounces_are_averaged()
+    {
+        // Inside of a glowing white sphere every ray sees the same light, the emission of every bounce
+        let mut world = World::new();
+        let mut material = Material::new(1., Color::white(), false);
+        material.emission = Color{r: 0.5, g: 0.5, b: 0.5};
+        world.add_shape(Arc::new(Sphere{radius: 10., position: Vector::new(), material}));
+        let ray = Ray::new(&Vector::new(), &Vector{x: 0., y: 0., z: -1.});
+        let view = View::look_at(&Vector{x: 0., y: 0., z: 5.}, &Vector::new(), &View::default_up()).unwrap();
+        for falloff in [0.5, 0.75, 3.].iter()
+        {
+            let mut settings = RenderSettings::new();
+            settings.max_ray_depth = 2;
+            settings.num_of_reflected_rays = 8;
+            settings.scattered_rays_falloff = *falloff;
+            let mut tracer = Tracer::new(&world, &settings, &view, &Lens::pinhole());
+            let mut num_of_rays = 0;
+            let color = tracer.render_sample(&ray, &mut num_of_rays).color;
+            assert_approx_eq!(color.r, 1.5, 1e-9);
+        }
+    }
+
+    #[test]
+    fn bounces_take_the_surface_color()
+    {
+        // Glowing sphere of a partly reflective orange material, every bounce passes on its albedo
+        let mut world = World::new();
+        let mut material = Material::new(0.8, Color{r: 1., g: 0.5, b: 0.25}, false);
+        material.emission = Color{r: 0.5, g: 0.5, b: 0.5};
+        world.add_shape(Arc::new(Sphere{radius: 10., position: Vector::new(), material}));
+        let ray = Ray::new(&Vector::new(), &Vector{x: 0., y: 0., z: -1.});
+        let view = View::look_at(&Vector{x: 0., y: 0., z: 5.}, &Vector::new(), &View::default_up()).unwrap();
+        let mut settings = RenderSettings::new();
+        settings.max_ray_depth = 2;
+        settings.num_of_reflected_rays = 8;
+        let mut tracer = Tracer::new(&world, &settings, &view, &Lens::pinhole());
+        let mut num_of_rays = 0;
+        let color = tracer.render_sample(&ray, &mut num_of_rays).color;
+        let expected = |albedo: f64| 0.5 * (1. + albedo + albedo * albedo);
+        assert_approx_eq!(color.r, expected(0.8), 1e-9);
+        assert_approx_eq!(color.g, expected(0.4), 1e-9);
+        assert_approx_eq!(color.b, expected(0.2), 1e-9);
+
+        // Red floor lit only by a white glowing sphere reflects red light
+        let mut world = World::new();
+        let mut neon = Material::new(0.5, Color::white(), false);
+        neon.emission = Color::white();
+        world.add_shape(Arc::new(Sphere{radius: 1., position: Vector{x: 0., y: -3., z: 0.}, material: neon}));
+        world.add_shape(Arc::new(Rectangle::new(&Vector{x: -10., y: 0., z: -10.}, &Vector{x: 20., y: 0., z: 0.},
+                                                &Vector{x: 0., y: 0., z: 20.}, &Material::new(0.5, Color{r: 1., g: 0.2, b: 0.2}, false))));
+        settings.max_ray_depth = 1;
+        settings.num_of_reflected_rays = 100;
+        let mut tracer = Tracer::new(&world, &settings, &view, &Lens::pinhole());
+        let floor_ray = Ray::new(&Vector{x: 1., y: -0.5, z: 0.}, &Vector{x: 0., y: 1., z: 0.});
+        let lit_floor = tracer.render_sample(&floor_ray, &mut num_of_rays).color;
+        assert!(lit_floor.r > 0.);
+        assert_approx_eq!(lit_floor.r / lit_floor.g, 5., 1e-9);
+    }
+
+    #[test]
+    fn light_behind_the_surface()
+    {
+        let mut world = World::new();
+        world.add_shape(Arc::new(Sphere{radius: 1.,
+                                       position: Vector{x: 0., y: 0., z: -5.},
+                                       material: Material::new(0.5, Color::white(), false)}));
+        world.add_light(Arc::new(Lightsource::new(&Vector{x: 0., y: 0., z: -10.}, 1000.)));
+        let ray = Ray::new(&Vector{x: 0., y: 0., z: 5.}, &Vector{x: 0., y: 0., z: -1.});
+        assert_eq!(direct_light(&world, &ray), Color::new());
+    }
+}
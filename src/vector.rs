@@ -60,6 +60,14 @@ impl ops::Mul<Vector> for Vector
     }
 }
 
+impl Default for Vector
+{
+    fn default() -> Vector
+    {
+        Vector::new()
+    }
+}
+
 impl Vector
 {
     pub fn new() -> Vector
@@ -73,12 +81,12 @@ impl Vector
     pub fn distance(&self) -> f64
     {
         let sum_of_squares = self.x.powi(2) + self.y.powi(2) + self.z.powi(2);
-        return sum_of_squares.sqrt();
+        sum_of_squares.sqrt()
     }
 
     pub fn dot(&self, other: Vector) -> f64
     {
-        return self.x * other.x + self.y * other.y + self.z * other.z;
+        self.x * other.x + self.y * other.y + self.z * other.z
     }
     pub fn rotate_x(&mut self, angle: f64)
     {
@@ -106,6 +114,36 @@ impl Vector
         self.y = y;
     }
 
+    // Returns the x (0), y (1) or z (2) component
+    pub fn axis(&self, axis: usize) -> f64
+    {
+        match axis{
+            0 => self.x,
+            1 => self.y,
+            _ => self.z
+        }
+    }
+
+    // Component-wise minimum of two vectors
+    pub fn min(&self, other: Vector) -> Vector
+    {
+        Vector {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z)
+        }
+    }
+
+    // Component-wise maximum of two vectors
+    pub fn max(&self, other: Vector) -> Vector
+    {
+        Vector {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z)
+        }
+    }
+
     pub fn normalized(&self) -> Vector
     {
         let length = self.distance();
@@ -120,6 +158,21 @@ impl Vector
     {
         normal * (self.dot(normal) * -2.) + *self
     }
+
+    // Direction of the ray refracted by Snell's law, both vectors have to be normalized and the normal
+    // has to point against the vector. Ratio is n1 / n2 of the refractive indices of both sides.
+    // Returns None on total internal reflection.
+    pub fn refraction(&self, normal: Vector, ratio: f64) -> Option<Vector>
+    {
+        let cos_incident = -self.dot(normal);
+        let sin_transmitted_squared = ratio * ratio * (1. - cos_incident * cos_incident);
+        if sin_transmitted_squared > 1.
+        {
+            return None;
+        }
+        let cos_transmitted = (1. - sin_transmitted_squared).sqrt();
+        Some(*self * ratio + normal * (ratio * cos_incident - cos_transmitted))
+    }
 }
 
 #[cfg(test)]
@@ -218,6 +271,23 @@ mod tests
         assert_approx_eq!(vec_a.z, 6.622590);
     }
 
+    #[test]
+    fn min_max()
+    {
+        let (vec_a, vec_b) = get_test_vectors();
+
+        let min = vec_a.min(vec_b);
+        assert_approx_eq!(min.x, -2.2);
+        assert_approx_eq!(min.y, 3.4);
+        assert_approx_eq!(min.z, 5.7);
+
+        let max = vec_a.max(vec_b);
+        assert_approx_eq!(max.x, 2.1);
+        assert_approx_eq!(max.y, 4.9);
+        assert_approx_eq!(max.z, 7.4);
+        assert_approx_eq!(max.axis(1), 4.9);
+    }
+
     #[test]
     fn normalized()
     {
@@ -234,6 +304,25 @@ mod tests
         assert_approx_eq!(normalized.z, -0.8);
     }
 
+    #[test]
+    fn refraction()
+    {
+        let normal = Vector{x: 0., y: 1., z: 0.};
+        // Straight through the surface
+        let refracted = Vector{x: 0., y: -1., z: 0.}.refraction(normal, 1. / 1.5).unwrap();
+        assert_approx_eq!(refracted.y, -1.);
+
+        // 45 degrees from the air to the glass
+        let incident = Vector{x: 1., y: -1., z: 0.}.normalized();
+        let refracted = incident.refraction(normal, 1. / 1.5).unwrap();
+        assert_approx_eq!(refracted.distance(), 1.);
+        assert_approx_eq!(refracted.x, std::f64::consts::FRAC_1_SQRT_2 / 1.5);
+        assert!(refracted.y < 0.);
+
+        // Same angle from the glass to the air is above the critical angle
+        assert!(incident.refraction(normal, 1.5).is_none());
+    }
+
     #[test]
     fn reflection()
     {
@@ -0,0 +1,370 @@
+// Camera model: where the camera stands, where it looks, its lens and how the image plane is mapped
+// to the primary rays. The y axis of the world grows downwards, so the default up is -Y.
+use crate::ray::Ray;
+use crate::settings::{RenderSettings, FovAxis};
+use crate::vector::Vector;
+use crate::sampler;
+use std::f64::consts;
+use std::fmt;
+
+// Orthonormal basis of the camera, always valid once created
+#[derive(Clone, Copy, Debug)]
+pub struct View
+{
+    eye: Vector,
+    forward: Vector,
+    right: Vector,
+    up: Vector,
+}
+
+impl View
+{
+    pub fn default_up() -> Vector
+    {
+        Vector{x: 0., y: -1., z: 0.}
+    }
+
+    // Camera at the eye looking at the target. The up vector only has to point roughly upwards,
+    // it is made perpendicular to the view direction. None when the target is at the eye or the
+    // up vector is parallel to the view direction.
+    pub fn look_at(eye: &Vector, target: &Vector, up: &Vector) -> Option<View>
+    {
+        View::look_in(eye, &(*target - *eye), up)
+    }
+
+    pub fn look_in(eye: &Vector, direction: &Vector, up: &Vector) -> Option<View>
+    {
+        if direction.distance() == 0.
+        {
+            return None;
+        }
+        let forward = direction.normalized();
+        let right = forward * *up;
+        // Relative, so that the length of the up vector doesn't matter
+        if right.distance() <= 1e-9 * up.distance()
+        {
+            return None;
+        }
+        let right = right.normalized();
+        Some(View{
+            eye: *eye,
+            forward,
+            right,
+            up: right * forward
+        })
+    }
+
+    pub fn eye(&self) -> Vector
+    {
+        self.eye
+    }
+
+    pub fn forward(&self) -> Vector
+    {
+        self.forward
+    }
+
+    pub fn right(&self) -> Vector
+    {
+        self.right
+    }
+
+    pub fn up(&self) -> Vector
+    {
+        self.up
+    }
+}
+
+// Thin lens, everything at the focus distance is sharp and the rest gets blurred the more,
+// the bigger the aperture is. Aperture 0 is a pinhole, where everything is sharp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lens
+{
+    pub aperture_radius: f64,
+    // Distance from the eye to the plane in focus, along the view direction
+    pub focus_distance: f64,
+}
+
+impl Lens
+{
+    pub fn pinhole() -> Lens
+    {
+        Lens{
+            aperture_radius: 0.,
+            focus_distance: 1.
+        }
+    }
+}
+
+// How the directions around the camera are laid out on the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection
+{
+    // Pinhole on the flat image plane, straight lines stay straight
+    Perspective,
+    // Parallel rays, the extent is the size of the visible area (in the world units) along the fov axis
+    Orthographic{extent: f64},
+    // Equidistant fisheye, the angle from the view direction grows linearly with the distance from
+    // the center of the image. The field of view can be up to 360 degrees.
+    Fisheye,
+    // Whole sphere around the camera, longitude along x and latitude along y, best with 2:1 images.
+    // Ignores the field of view.
+    Equirectangular,
+}
+
+impl Projection
+{
+    // Names used in the scene files and on the command line, the extent of the orthographic
+    // projection is set separately
+    pub fn from_name(name: &str) -> Option<Projection>
+    {
+        match name
+        {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic{extent: 10.}),
+            "fisheye" => Some(Projection::Fisheye),
+            "equirectangular" => Some(Projection::Equirectangular),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Projection
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Projection::Perspective => write!(f, "perspective"),
+            Projection::Orthographic{extent} => write!(f, "orthographic (extent {})", extent),
+            Projection::Fisheye => write!(f, "fisheye"),
+            Projection::Equirectangular => write!(f, "equirectangular"),
+        }
+    }
+}
+
+// Maps the points of the image to the primary rays with the projection from the settings.
+// Pixels are square, so the size of the image along the other axis than the fov one follows
+// from the aspect ratio of the image.
+#[derive(Clone, Copy, Debug)]
+pub struct PrimaryRays
+{
+    view: View,
+    lens: Lens,
+    projection: Projection,
+    width: f64,
+    height: f64,
+    // Half of the size of the image: of the image plane at distance 1 (perspective), of the visible
+    // area (orthographic) or the angles in radians (fisheye and equirectangular)
+    half_width: f64,
+    half_height: f64,
+    // Half of the field of view in radians, the fisheye sees only the circle of this radius
+    half_fov: f64,
+}
+
+impl PrimaryRays
+{
+    pub fn new(view: &View, lens: &Lens, settings: &RenderSettings) -> PrimaryRays
+    {
+        let aspect_ratio = settings.width as f64 / settings.height as f64;
+        let half_size = match settings.projection
+        {
+            Projection::Perspective => (settings.fov.to_radians() / 2.).tan(),
+            Projection::Orthographic{extent} => extent / 2.,
+            Projection::Fisheye | Projection::Equirectangular => settings.fov.to_radians() / 2.
+        };
+        let (half_width, half_height) = match (settings.projection, settings.fov_axis)
+        {
+            (Projection::Equirectangular, _) => (consts::PI, consts::FRAC_PI_2),
+            (_, FovAxis::Horizontal) => (half_size, half_size / aspect_ratio),
+            (_, FovAxis::Vertical) => (half_size * aspect_ratio, half_size)
+        };
+        PrimaryRays{
+            view: *view,
+            lens: *lens,
+            projection: settings.projection,
+            width: settings.width as f64,
+            height: settings.height as f64,
+            half_width,
+            half_height,
+            half_fov: settings.fov.to_radians() / 2.
+        }
+    }
+
+    // Ray through the point of the image given in pixels, (0, 0) is the top left corner of the image
+    // and the center of the first pixel is (0.5, 0.5). The lens sample is a random point of the unit
+    // square that picks where the ray crosses the lens. The direction is normalized.
+    // None for the points that the projection doesn't cover (corners of the fisheye image).
+    pub fn ray(&self, x: f64, y: f64, lens_sample: (f64, f64)) -> Option<Ray>
+    {
+        let horizontal = (2. * x / self.width - 1.) * self.half_width;
+        let vertical = (1. - 2. * y / self.height) * self.half_height;
+        let view = &self.view;
+        let (start, direction) = match self.projection
+        {
+            Projection::Perspective => (view.eye, view.forward + view.right * horizontal + view.up * vertical),
+            Projection::Orthographic{..} => (view.eye + view.right * horizontal + view.up * vertical, view.forward),
+            Projection::Fisheye => {
+                let angle = horizontal.hypot(vertical);
+                if angle > self.half_fov
+                {
+                    return None;
+                }
+                let around = vertical.atan2(horizontal);
+                let sideways = (view.right * around.cos() + view.up * around.sin()) * angle.sin();
+                (view.eye, view.forward * angle.cos() + sideways)
+            },
+            Projection::Equirectangular => {
+                let sideways = view.forward * horizontal.cos() + view.right * horizontal.sin();
+                (view.eye, sideways * vertical.cos() + view.up * vertical.sin())
+            }
+        };
+        if self.lens.aperture_radius <= 0.
+        {
+            return Some(Ray::new(&start, &direction.normalized()));
+        }
+        // Every ray through the lens meets the pinhole ray in focus: on the plane at the focus distance
+        // for the flat projections, at the focus distance along the ray for the panoramic ones
+        let focus_point = match self.projection
+        {
+            Projection::Perspective | Projection::Orthographic{..} => start + direction * self.lens.focus_distance,
+            Projection::Fisheye | Projection::Equirectangular => start + direction.normalized() * self.lens.focus_distance
+        };
+        let (lens_x, lens_y) = sampler::concentric_disk(lens_sample);
+        let start = start + (view.right * lens_x + view.up * lens_y) * self.lens.aperture_radius;
+        Some(Ray::new(&start, &(focus_point - start).normalized()))
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::view::{View, Lens, PrimaryRays, Projection};
+    use crate::settings::{RenderSettings, FovAxis};
+    use crate::vector::Vector;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn assert_vectors_eq(a: Vector, b: Vector)
+    {
+        assert!((a - b).distance() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn basis()
+    {
+        let eye = Vector{x: 1., y: 2., z: 3.};
+        let view = View::look_at(&eye, &Vector{x: 1., y: 2., z: -7.}, &Vector{x: 0., y: -5., z: 1.}).unwrap();
+        assert_vectors_eq(view.forward(), Vector{x: 0., y: 0., z: -1.});
+        assert_vectors_eq(view.up(), Vector{x: 0., y: -1., z: 0.});
+        assert_vectors_eq(view.right(), Vector{x: -1., y: 0., z: 0.});
+
+        // Looking down at the floor
+        let view = View::look_in(&eye, &Vector{x: 0., y: 1., z: -1.}, &View::default_up()).unwrap();
+        assert_approx_eq!(view.forward().dot(view.up()), 0.);
+        assert_approx_eq!(view.up().distance(), 1.);
+        assert!(view.up().y < 0.);
+
+        assert!(View::look_at(&eye, &eye, &View::default_up()).is_none());
+        assert!(View::look_in(&eye, &Vector{x: 0., y: 2., z: 0.}, &View::default_up()).is_none());
+    }
+
+    #[test]
+    fn image_plane()
+    {
+        let mut settings = RenderSettings::new();
+        settings.width = 200;
+        settings.height = 100;
+        settings.fov = 90.;
+        // Looking along +X, which the old rotation based camera couldn't do
+        let view = View::look_in(&Vector::new(), &Vector{x: 1., y: 0., z: 0.}, &View::default_up()).unwrap();
+        let rays = PrimaryRays::new(&view, &Lens::pinhole(), &settings);
+        let ray = |x: f64, y: f64| rays.ray(x, y, (0.5, 0.5)).unwrap();
+
+        assert_vectors_eq(ray(100., 50.).direction, Vector{x: 1., y: 0., z: 0.});
+        // Edges of the image are 45 degrees to the sides and half of it up and down
+        assert_vectors_eq(ray(200., 50.).direction, Vector{x: 1., y: 0., z: -1.}.normalized());
+        assert_vectors_eq(ray(100., 0.).direction, Vector{x: 1., y: -0.5, z: 0.}.normalized());
+
+        // Points on the image that are evenly spaced hit evenly spaced points of a wall
+        let wall_hit = |x: f64| {
+            let direction = ray(x, 50.).direction;
+            direction.z / direction.x
+        };
+        assert_approx_eq!(wall_hit(150.) - wall_hit(100.), wall_hit(200.) - wall_hit(150.));
+
+        settings.fov_axis = FovAxis::Vertical;
+        let rays = PrimaryRays::new(&view, &Lens::pinhole(), &settings);
+        assert_vectors_eq(rays.ray(100., 0., (0.5, 0.5)).unwrap().direction, Vector{x: 1., y: -1., z: 0.}.normalized());
+        assert_vectors_eq(rays.ray(200., 50., (0.5, 0.5)).unwrap().direction, Vector{x: 1., y: 0., z: -2.}.normalized());
+    }
+
+    #[test]
+    fn thin_lens()
+    {
+        let settings = RenderSettings::new();
+        let view = View::look_in(&Vector::new(), &Vector{x: 0., y: 0., z: -1.}, &View::default_up()).unwrap();
+        let lens = Lens{aperture_radius: 0.5, focus_distance: 10.};
+        let rays = PrimaryRays::new(&view, &lens, &settings);
+
+        // Rays through different parts of the lens start in different places,
+        // but meet again on the plane in focus
+        let samples = [(0., 0.), (1., 0.3), (0.2, 0.9), (0.5, 0.5)];
+        let pinhole_ray = PrimaryRays::new(&view, &Lens::pinhole(), &settings).ray(300., 70., (0.5, 0.5)).unwrap();
+        let in_focus = pinhole_ray.start_position + pinhole_ray.direction * (10. / -pinhole_ray.direction.z);
+        for sample in samples.iter()
+        {
+            let ray = rays.ray(300., 70., *sample).unwrap();
+            assert_approx_eq!(ray.start_position.z, 0.);
+            assert!(ray.start_position.distance() <= 0.5 + 1e-9);
+            let on_plane = ray.start_position + ray.direction * (10. / -ray.direction.z);
+            assert_vectors_eq(on_plane, in_focus);
+        }
+        assert!(rays.ray(300., 70., (0., 0.)).unwrap().start_position.distance() > 0.4);
+    }
+
+    #[test]
+    fn projections()
+    {
+        let mut settings = RenderSettings::new();
+        settings.width = 200;
+        settings.height = 100;
+        let view = View::look_in(&Vector::new(), &Vector{x: 0., y: 0., z: -1.}, &View::default_up()).unwrap();
+        let ray = |settings: &RenderSettings, x: f64, y: f64| PrimaryRays::new(&view, &Lens::pinhole(), settings).ray(x, y, (0.5, 0.5));
+
+        // Parallel rays that start on the plane through the eye
+        settings.projection = Projection::Orthographic{extent: 8.};
+        let corner = ray(&settings, 0., 0.).unwrap();
+        assert_vectors_eq(corner.direction, Vector{x: 0., y: 0., z: -1.});
+        assert_vectors_eq(corner.start_position, Vector{x: 4., y: -2., z: 0.});
+
+        // Angle grows with the distance from the center, up to the back of the camera
+        settings.projection = Projection::Fisheye;
+        settings.fov = 180.;
+        assert_vectors_eq(ray(&settings, 100., 50.).unwrap().direction, Vector{x: 0., y: 0., z: -1.});
+        assert_vectors_eq(ray(&settings, 200., 50.).unwrap().direction, Vector{x: -1., y: 0., z: 0.});
+        assert_vectors_eq(ray(&settings, 150., 50.).unwrap().direction, Vector{x: -1., y: 0., z: -1.}.normalized());
+        settings.fov = 360.;
+        assert_vectors_eq(ray(&settings, 200., 50.).unwrap().direction, Vector{x: 0., y: 0., z: 1.});
+        assert!(ray(&settings, 0., 0.).is_none());
+        // Only the circle of the field of view, the corners of the image stay empty
+        settings.fov = 180.;
+        assert!(ray(&settings, 0.5, 0.5).is_none());
+        assert!(ray(&settings, 100., 0.5).is_some());
+        settings.width = 100;
+        assert!(ray(&settings, 0.5, 0.5).is_none());
+        assert!(ray(&settings, 99.5, 99.5).is_none());
+        assert!(ray(&settings, 50., 10.).is_some());
+        settings.width = 200;
+
+        // Longitude across the image, latitude from the top to the bottom
+        settings.projection = Projection::Equirectangular;
+        assert_vectors_eq(ray(&settings, 100., 50.).unwrap().direction, Vector{x: 0., y: 0., z: -1.});
+        assert_vectors_eq(ray(&settings, 150., 50.).unwrap().direction, Vector{x: -1., y: 0., z: 0.});
+        assert_vectors_eq(ray(&settings, 0., 50.).unwrap().direction, Vector{x: 0., y: 0., z: 1.});
+        assert_vectors_eq(ray(&settings, 30., 0.).unwrap().direction, Vector{x: 0., y: -1., z: 0.});
+        assert_vectors_eq(ray(&settings, 100., 75.).unwrap().direction, Vector{x: 0., y: 1., z: -1.}.normalized());
+
+        assert_eq!(Projection::from_name("fisheye"), Some(Projection::Fisheye));
+        assert_eq!(Projection::from_name("cylindrical"), None);
+    }
+}
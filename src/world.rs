@@ -1,13 +1,28 @@
-use crate::shapes::Shape;
+use crate::shapes::{Shape, Hit};
 use crate::ray::Ray;
-use crate::lightsource::Lightsource;
-use std::rc::Rc;
+use crate::lightsource::Light;
+use crate::bvh::Bvh;
+use std::sync::Arc;
 use std::f64;
-use crate::vector::Vector;
 
 pub struct World{
-    shapes: Vec<Rc<dyn Shape>>,
-    pub lights: Vec<Rc<Lightsource>>
+    shapes: Vec<Arc<dyn Shape>>,
+    // Material id of every shape, only used to label the shapes in the material id pass
+    material_ids: Vec<u32>,
+    // Indices of the shapes in the hierarchy (the hierarchy indexes this vector)
+    bounded_shapes: Vec<usize>,
+    // Indices of the shapes that have no bounding box, they are checked against every ray
+    unbounded_shapes: Vec<usize>,
+    // Hierarchy over the bounded shapes, None until build_bvh is called
+    bvh: Option<Bvh>,
+    pub lights: Vec<Arc<dyn Light>>
+}
+
+impl Default for World{
+    fn default() -> World
+    {
+        World::new()
+    }
 }
 
 impl World{
@@ -15,48 +30,261 @@ impl World{
     {
         World{
             shapes: vec![],
+            material_ids: vec![],
+            bounded_shapes: vec![],
+            unbounded_shapes: vec![],
+            bvh: None,
             lights: vec![]
         }
     }
 
-    pub fn add_shape(& mut self, shape: Rc<dyn Shape>)
+    pub fn add_shape(& mut self, shape: Arc<dyn Shape>)
+    {
+        self.add_shape_with_material_id(shape, 0);
+    }
+
+    // Shapes that share the material should share the id, 0 means that the material is unknown
+    pub fn add_shape_with_material_id(&mut self, shape: Arc<dyn Shape>, material_id: u32)
+    {
+        self.shapes.push(shape);
+        self.material_ids.push(material_id);
+        // Hierarchy doesn't know about the new shape, so it has to be built again
+        self.bvh = None;
+    }
+
+    // Shapes are indexed in the order they were added
+    pub fn shape(&self, idx: usize) -> &Arc<dyn Shape>
     {
-        self.shapes.push( shape);
+        &self.shapes[idx]
     }
 
-    pub fn add_light(&mut self, light: Rc<Lightsource>)
+    pub fn material_id(&self, idx: usize) -> u32
+    {
+        self.material_ids[idx]
+    }
+
+    pub fn add_light(&mut self, light: Arc<dyn Light>)
     {
         self.lights.push(light);
     }
 
-    pub fn item_that_collide(&self, ray: &Ray) -> Option<(Rc<dyn Shape>, Vector)>
+    // Builds the bounding volume hierarchy over all shapes added so far.
+    // Without it every query checks every shape.
+    pub fn build_bvh(&mut self)
+    {
+        let mut bounds = Vec::with_capacity(self.shapes.len());
+        self.bounded_shapes.clear();
+        self.unbounded_shapes.clear();
+        for (idx, shape) in self.shapes.iter().enumerate()
+        {
+            match shape.bounding_box()
+            {
+                Some(bounding_box) => {
+                    bounds.push(bounding_box);
+                    self.bounded_shapes.push(idx);
+                },
+                None => self.unbounded_shapes.push(idx)
+            }
+        }
+        self.bvh = Some(Bvh::build(&bounds));
+    }
+
+    // Returns the distance to the collision point of the ray with the shape and the hit itself
+    fn collide_with_shape(&self, idx: usize, ray: &Ray) -> Option<(f64, Hit)>
+    {
+        let shape = &self.shapes[idx];
+        // Check if ray will even collide with shape to avoid unnecessary calculations
+        if shape.can_collide(ray)
+        {
+            if let Some(hit) = shape.collision(ray)
+            {
+                return Some(((hit.point - ray.start_position).distance(), hit));
+            }
+        }
+        None
+    }
+
+    pub fn item_that_collide(&self, ray: &Ray) -> Option<(Arc<dyn Shape>, Hit)>
+    {
+        self.closest_collision(ray).map(|(idx, _, hit)| (Arc::clone(&self.shapes[idx]), hit))
+    }
+
+    // Checks every shape, regardless of the hierarchy
+    pub fn item_that_collide_linear(&self, ray: &Ray) -> Option<(Arc<dyn Shape>, Hit)>
+    {
+        self.closest_collision_linear(ray).map(|(idx, _, hit)| (Arc::clone(&self.shapes[idx]), hit))
+    }
+
+    // Index of the closest shape that collides with the ray, the distance to the collision and the hit
+    pub fn closest_collision(&self, ray: &Ray) -> Option<(usize, f64, Hit)>
+    {
+        let bvh = match &self.bvh
+        {
+            Some(bvh) => bvh,
+            None => return self.closest_collision_linear(ray)
+        };
+        // Calculate the distance to closest collision, because ray will end in
+        // the first collision
+        let mut closest: Option<(usize, f64, Hit)> = None;
+        for idx in self.unbounded_shapes.iter()
+        {
+            if let Some((distance, hit)) = self.collide_with_shape(*idx, ray)
+            {
+                if closest.is_none_or(|(_, smallest_distance, _)| distance < smallest_distance)
+                {
+                    closest = Some((*idx, distance, hit));
+                }
+            }
+        }
+        let smallest_distance = closest.map_or(f64::MAX, |(_, distance, _)| distance);
+        let bounded_shapes = &self.bounded_shapes;
+        if let Some((idx, distance, hit)) = bvh.closest_hit(ray, smallest_distance, |idx| self.collide_with_shape(bounded_shapes[idx], ray))
+        {
+            closest = Some((bounded_shapes[idx], distance, hit));
+        }
+        closest
+    }
+
+    pub fn closest_collision_linear(&self, ray: &Ray) -> Option<(usize, f64, Hit)>
     {
-        let mut closest_item_that_collide: Option<Rc<dyn Shape>> = None;
-        let mut closest_collision_point: Option<Vector> = None;
-        let mut smallest_distance = f64::MAX;
-        for shape in self.shapes.iter()
+        let mut closest: Option<(usize, f64, Hit)> = None;
+        for idx in 0..self.shapes.len()
         {
-            // Check if ray will even collide with shape to avoid unnecessary calculations
-            if shape.can_collide(&ray)
+            if let Some((distance, hit)) = self.collide_with_shape(idx, ray)
             {
-                if let Some(collision_point) = shape.collision_point(&ray)
+                // Calculate the distance to closest collision, because ray will end in
+                // the first collision
+                if closest.is_none_or(|(_, smallest_distance, _)| distance < smallest_distance)
                 {
-                    // Calculate the distance to closest collision, because ray will end in
-                    // the first collision
-                    let distance = (collision_point - ray.start_position).distance();
-                    if distance < smallest_distance
-                    {
-                        smallest_distance = distance;
-                        closest_item_that_collide = Some(Rc::clone(&shape));
-                        closest_collision_point = Some(collision_point);
-                    }
+                    closest = Some((idx, distance, hit));
                 }
             }
         }
-        match closest_item_that_collide
+        closest
+    }
+
+    // Index of the closest light whose surface the ray hits and the distance to it. Lights are not
+    // in the hierarchy, there are only a few of them.
+    pub fn closest_light(&self, ray: &Ray) -> Option<(usize, f64)>
+    {
+        self.lights.iter().enumerate()
+            .filter_map(|(idx, light)| light.intersect(ray).map(|distance| (idx, distance)))
+            .min_by(|(_, first), (_, second)| first.total_cmp(second))
+    }
+
+    // Returns true if the ray collides with anything closer than max_distance (i.e. between the point
+    // and the light). Stops at the first such collision, so it is cheaper than closest_collision when
+    // the closest item doesn't matter (i.e. shadows).
+    pub fn occluded(&self, ray: &Ray, max_distance: f64) -> bool
+    {
+        // Only the point matters, not the surface at it
+        let blocks = |idx: usize| {
+            let shape = &self.shapes[idx];
+            shape.can_collide(ray) && shape.collision_point(ray).is_some_and(|point| (point - ray.start_position).distance() < max_distance)
+        };
+        match &self.bvh
+        {
+            Some(bvh) => {
+                self.unbounded_shapes.iter().any(|idx| blocks(*idx))
+                    || bvh.any_hit(ray, max_distance, |idx| blocks(self.bounded_shapes[idx]))
+            },
+            None => (0..self.shapes.len()).any(blocks)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::world::World;
+    use crate::shapes::{Sphere, Rectangle};
+    use crate::ray::Ray;
+    use crate::vector::Vector;
+    use crate::material::Material;
+    use std::sync::Arc;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    fn get_world() -> World
+    {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut world = World::new();
+        for _ in 0..500
+        {
+            world.add_shape(Arc::new(Sphere{radius: rng.gen_range(0.1, 1.),
+                                            position: Vector{x: rng.gen_range(-20., 20.),
+                                                             y: rng.gen_range(-20., 20.),
+                                                             z: rng.gen_range(-20., 20.)},
+                                            material: Material::default()}));
+        }
+        world.add_shape(Arc::new(Rectangle::new(&Vector{x: -30., y: 15., z: -30.},
+                                                &Vector{x: 60., y: 0., z: 0.},
+                                                &Vector{x: 0., y: 0., z: 60.},
+                                                &Material::default())));
+        world
+    }
+
+    #[test]
+    fn bvh_gives_same_collisions_as_linear_search()
+    {
+        let mut world = get_world();
+        world.build_bvh();
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut num_of_collisions = 0;
+        for _ in 0..2000
+        {
+            let start_position = Vector{x: rng.gen_range(-25., 25.), y: rng.gen_range(-25., 25.), z: rng.gen_range(-25., 25.)};
+            let direction = Vector{x: rng.gen_range(-1., 1.), y: rng.gen_range(-1., 1.), z: rng.gen_range(-1., 1.)};
+            let ray = Ray::new(&start_position, &direction);
+
+            let linear = world.item_that_collide_linear(&ray);
+            let bvh = world.item_that_collide(&ray);
+            assert_eq!(linear.is_some(), bvh.is_some());
+            assert_eq!(linear.is_some(), world.occluded(&ray, f64::INFINITY));
+            if let (Some((linear_item, linear_hit)), Some((bvh_item, bvh_hit))) = (linear, bvh)
+            {
+                let (linear_point, bvh_point) = (linear_hit.point, bvh_hit.point);
+                num_of_collisions += 1;
+                // Only the collisions before the end of the ray count
+                let closest_distance = (linear_point - start_position).distance();
+                assert!(world.occluded(&ray, closest_distance + 1e-6));
+                assert!(!world.occluded(&ray, closest_distance - 1e-6));
+                assert!(Arc::ptr_eq(&linear_item, &bvh_item));
+                assert!((linear_point - bvh_point).distance() < 1e-9);
+
+                let (idx, distance, _) = world.closest_collision(&ray).unwrap();
+                assert!(Arc::ptr_eq(world.shape(idx), &bvh_item));
+                assert!((distance - (bvh_point - start_position).distance()).abs() < 1e-9);
+            }
+        }
+        // Make sure that the test checks something
+        assert!(num_of_collisions > 100);
+    }
+
+    #[test]
+    fn occlusion_ends_at_the_light()
+    {
+        let mut world = World::new();
+        world.add_shape(Arc::new(Sphere{radius: 1., position: Vector{x: 0., y: 0., z: -5.}, material: Material::default()}));
+        let ray = Ray::new(&Vector::new(), &Vector{x: 0., y: 0., z: -2.});
+        for _ in 0..2
         {
-            Some(item) => Some((item, closest_collision_point.unwrap())),
-            None => None
+            // Sphere is behind the light 3 units away, but in front of the one 5 units away
+            assert!(!world.occluded(&ray, 3.));
+            assert!(world.occluded(&ray, 5.));
+            assert!(!world.occluded(&Ray::new(&Vector::new(), &Vector{x: 0., y: 1., z: 0.}), f64::INFINITY));
+            world.build_bvh();
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn adding_shape_drops_bvh()
+    {
+        let mut world = get_world();
+        world.build_bvh();
+        assert!(world.bvh.is_some());
+        world.add_shape_with_material_id(Arc::new(Sphere{radius: 1., position: Vector::new(), material: Material::default()}), 3);
+        assert!(world.bvh.is_none());
+        assert_eq!(world.material_id(0), 0);
+        assert_eq!(world.material_id(501), 3);
+    }
+}